@@ -3,9 +3,15 @@
  */
 use anyhow::Context;
 use std::env;
+
+#[cfg(windows)]
 use winrt_toast::{Text, Toast, ToastManager};
 
+#[cfg(not(windows))]
+use std::process::Command;
+
 
+#[cfg(windows)]
 #[tauri::command]
 pub fn send_notify(title: &str, body: &str) -> Result<(), tauri::Error> {
     let aumid = env!("AUMID");
@@ -17,3 +23,21 @@ pub fn send_notify(title: &str, body: &str) -> Result<(), tauri::Error> {
 
     Ok(())
 }
+
+#[cfg(not(windows))]
+#[tauri::command]
+pub fn send_notify(title: &str, body: &str) -> Result<(), tauri::Error> {
+    // デスクトップ通知は freedesktop の notify-send コマンドに任せる
+    let status = Command::new("notify-send")
+        .arg(format!("--app-name={}", env!("CARGO_PKG_NAME")))
+        .arg(title)
+        .arg(body)
+        .status()
+        .context("Failed to run notify-send")?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("notify-send exited with {}", status).into());
+    }
+
+    Ok(())
+}
@@ -6,6 +6,7 @@ use clap::Parser;
 use commands::info;
 use commands::license;
 use commands::notify;
+use log::error;
 use utils::keyhook;
 use utils::setting;
 
@@ -14,6 +15,138 @@ use utils::setting;
 struct Args {
     #[arg(short = 'd', long)]
     debug: bool,
+
+    /// Input source to use (win32, evdev, x11). Detected automatically if omitted
+    #[arg(long, value_name = "SOURCE")]
+    input: Option<String>,
+
+    /// Read key input from the given evdev device or recorded dump (repeatable)
+    #[cfg(all(target_os = "linux", feature = "evdev"))]
+    #[arg(long = "evdev-device", value_name = "PATH")]
+    evdev_devices: Vec<std::path::PathBuf>,
+
+    /// IME composition provider to use (win32, ibus, fcitx). Detected automatically if omitted
+    #[arg(long, value_name = "PROVIDER")]
+    ime: Option<String>,
+
+    /// Connect the IME composition provider to the given D-Bus address instead of the default bus
+    #[cfg(all(target_os = "linux", feature = "dbus-ime"))]
+    #[arg(long, value_name = "ADDRESS")]
+    ime_bus: Option<String>,
+
+    /// Pointer source to use (win32, evdev, x11) when mouse capture is enabled. Detected automatically if omitted
+    #[arg(long, value_name = "SOURCE")]
+    pointer: Option<String>,
+
+    /// Replay key input from a recorded JSON Lines file instead of the keyboard
+    #[arg(long, value_name = "FILE")]
+    replay: Option<std::path::PathBuf>,
+
+    /// Replay speed multiplier (0 replays without waiting, otherwise 0.001 to 1000)
+    #[arg(
+        long,
+        value_name = "SPEED",
+        default_value_t = 1.0,
+        requires = "replay",
+        value_parser = parse_replay_speed
+    )]
+    replay_speed: f64,
+
+    /// Record key input to a JSON Lines file. Typed characters are stored only as classes
+    #[arg(long, value_name = "FILE")]
+    record: Option<std::path::PathBuf>,
+}
+
+fn parse_replay_speed(s: &str) -> Result<f64, String> {
+    //! 再生速度の倍率をパースする. 待ち時間が極端な値にならないように範囲を制限する
+    const RANGE: std::ops::RangeInclusive<f64> = 0.001..=1000.0;
+    let speed: f64 = s.parse().map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if speed == 0.0 || RANGE.contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(format!(
+            "must be 0 or between {} and {}",
+            RANGE.start(),
+            RANGE.end()
+        ))
+    }
+}
+
+fn select_input_source(args: &Args) -> Option<Box<dyn keyhook::InputSource>> {
+    //! コマンドライン引数から使用する入力ソースを選択する
+    if let Some(path) = &args.replay {
+        return Some(Box::new(keyhook::ReplaySource::new(
+            path.clone(),
+            args.replay_speed,
+        )));
+    }
+
+    #[cfg(all(target_os = "linux", feature = "evdev"))]
+    if !args.evdev_devices.is_empty() {
+        return Some(Box::new(keyhook::EvdevSource::with_paths(
+            args.evdev_devices.clone(),
+        )));
+    }
+
+    match &args.input {
+        Some(name) => {
+            let source = keyhook::input_source_by_name(name);
+            if source.is_none() {
+                error!("Input source {} is not available on this platform", name);
+            }
+            source
+        }
+        None => keyhook::default_input_source(),
+    }
+}
+
+fn select_composition_provider(args: &Args) -> Option<Box<dyn keyhook::CompositionProvider>> {
+    //! コマンドライン引数から使用する IME の変換状態の提供元を選択する.
+    //! 記録したキー入力を再生する場合は、記録時の変換状態を使用するため選択しない.
+    if args.replay.is_some() {
+        return None;
+    }
+
+    #[cfg(all(target_os = "linux", feature = "dbus-ime"))]
+    if let Some(address) = &args.ime_bus {
+        let framework = match args.ime.as_deref() {
+            Some("ibus") => keyhook::ImeFramework::IBus,
+            _ => keyhook::ImeFramework::Fcitx,
+        };
+        return Some(Box::new(keyhook::DbusComposition::with_address(
+            framework, address,
+        )));
+    }
+
+    match &args.ime {
+        Some(name) => {
+            let provider = keyhook::composition_provider_by_name(name);
+            if provider.is_none() {
+                error!("IME composition provider {} is not available on this platform", name);
+            }
+            provider
+        }
+        None => keyhook::default_composition_provider(),
+    }
+}
+
+fn select_pointer_source(args: &Args) -> Option<Box<dyn keyhook::PointerSource>> {
+    //! 設定とコマンドライン引数から使用するマウスの入力ソースを選択する.
+    //! マウスの入力の取得が無効な場合と、記録したキー入力を再生する場合は選択しない.
+    if args.replay.is_some() || !setting::get_pointer().enabled {
+        return None;
+    }
+
+    match &args.pointer {
+        Some(name) => {
+            let source = keyhook::pointer_source_by_name(name);
+            if source.is_none() {
+                error!("Pointer source {} is not available on this platform", name);
+            }
+            source
+        }
+        None => keyhook::default_pointer_source(),
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -29,7 +162,17 @@ pub fn run() {
     // ロガーの初期化を一番最初に行う
     setting::init_logger(&args.debug);
 
-    keyhook::init_keyhook();
+    let recorder = args.record.as_deref().and_then(|path| {
+        keyhook::Recorder::create(path)
+            .inspect_err(|e| error!("Failed to start recording: {:#}", e))
+            .ok()
+    });
+    keyhook::init_keyhook(
+        select_input_source(&args),
+        select_composition_provider(&args),
+        select_pointer_source(&args),
+        recorder,
+    );
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
@@ -43,7 +186,58 @@ pub fn run() {
             utils::setting::get_count, utils::setting::set_count,
             utils::setting::get_interval, utils::setting::set_interval,
             utils::setting::get_afterallow,  utils::setting::set_afterallow,
+            utils::setting::get_correction_weights, utils::setting::set_correction_weights,
+            utils::setting::get_latency, utils::setting::set_latency,
+            utils::setting::get_window, utils::setting::set_window,
+            utils::setting::get_window_length, utils::setting::set_window_length,
+            utils::setting::get_min_samples, utils::setting::set_min_samples,
+            utils::setting::get_detector, utils::setting::set_detector,
+            utils::setting::get_key_classes, utils::setting::set_key_classes,
+            utils::setting::get_alert, utils::setting::set_alert,
+            utils::setting::get_baseline, utils::setting::set_baseline,
+            utils::setting::get_ime_mode, utils::setting::set_ime_mode,
+            utils::setting::get_caps_lock, utils::setting::set_caps_lock,
+            utils::setting::get_pointer, utils::setting::set_pointer,
+            utils::setting::get_hardware, utils::setting::set_hardware,
+            utils::keyhook::get_mistype_stats,
+            utils::keyhook::get_typing_metrics,
+            utils::keyhook::get_correction_latency,
+            utils::keyhook::get_mode_mishaps,
+            utils::keyhook::get_caps_lock_accidents,
+            utils::keyhook::get_hardware_faults,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                keyhook::shutdown_keyhook();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_speed_accepts_zero_and_range() {
+        //! 再生速度は 0 (待ち時間なし) と 0.001 から 1000 までの倍率を受け付けること
+        for (s, speed) in [
+            ("0", 0.0),
+            ("0.001", 0.001),
+            ("1", 1.0),
+            ("2.5", 2.5),
+            ("1000", 1000.0),
+        ] {
+            assert_eq!(parse_replay_speed(s), Ok(speed), "{}", s);
+        }
+    }
+
+    #[test]
+    fn replay_speed_rejects_out_of_range() {
+        //! 範囲外の倍率や数値でない値は、エラーとすること
+        for s in ["0.0001", "1001", "-1", "inf", "NaN", "fast", ""] {
+            assert!(parse_replay_speed(s).is_err(), "{}", s);
+        }
+    }
 }
@@ -0,0 +1,221 @@
+//! duration with explicit units for config files
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// 単位の接尾辞と、その単位のミリ秒数. 文字列にする際は上から順に割り切れる単位を使用する
+const UNITS: [(&str, u64); 4] = [("h", 3_600_000), ("m", 60_000), ("s", 1_000), ("ms", 1)];
+
+/// 設定ファイルで使用する時間.
+/// `"5m"`, `"30s"`, `"1h30m"` のように単位付きの文字列で表す.
+/// 単位のない整数はエラーとする (以前の設定ファイルの `interval` のみ `deserialize_legacy_minutes` で分として扱う).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConfigDuration(Duration);
+
+impl ConfigDuration {
+    pub const fn from_secs(secs: u64) -> Self {
+        //! 秒数から作成する
+        Self(Duration::from_secs(secs))
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        //! ミリ秒数から作成する
+        Self(Duration::from_millis(millis))
+    }
+
+    pub const fn from_mins(mins: u64) -> Self {
+        //! 分数から作成する
+        Self(Duration::from_secs(mins * 60))
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        //! `std::time::Duration` に変換する
+        self.0
+    }
+}
+
+impl fmt::Display for ConfigDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = self.0.as_millis() as u64;
+        if ms == 0 {
+            return write!(f, "0s");
+        }
+        let (unit, unit_ms) = UNITS
+            .iter()
+            .find(|(_, unit_ms)| ms.is_multiple_of(*unit_ms))
+            .expect("1ms divides any duration");
+        write!(f, "{}{}", ms / unit_ms, unit)
+    }
+}
+
+impl FromStr for ConfigDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //! `"1h30m"` のような、数値と単位の組の並びをパースする
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty duration".to_string());
+        }
+
+        let mut total = Duration::ZERO;
+        let mut rest = s;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if digits == 0 {
+                return Err(format!("invalid duration \"{}\": expected a number", s));
+            }
+            let value: u64 = rest[..digits]
+                .parse()
+                .map_err(|e| format!("invalid duration \"{}\": {}", s, e))?;
+            rest = &rest[digits..];
+
+            let unit_len = rest
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let unit = rest[..unit_len].trim();
+            let Some((_, unit_ms)) = UNITS.iter().find(|(name, _)| *name == unit) else {
+                return Err(format!(
+                    "invalid duration \"{}\": unknown unit \"{}\" (use h, m, s or ms)",
+                    s, unit
+                ));
+            };
+            let ms = value
+                .checked_mul(*unit_ms)
+                .ok_or_else(|| format!("invalid duration \"{}\": too large", s))?;
+            total += Duration::from_millis(ms);
+            rest = &rest[unit_len..];
+        }
+
+        Ok(Self(total))
+    }
+}
+
+impl Serialize for ConfigDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// 文字列から `ConfigDuration` を読み込む. `legacy_minutes` の場合のみ、単位のない整数を分として受け付ける
+struct Visitor {
+    legacy_minutes: bool,
+}
+
+impl Visitor {
+    fn unitless<E: de::Error>(&self, v: u64) -> Result<ConfigDuration, E> {
+        //! 単位のない整数を分として変換する. 互換性が必要な項目以外ではエラーとする
+        if self.legacy_minutes {
+            Ok(ConfigDuration::from_mins(v))
+        } else {
+            Err(E::custom(format!(
+                "duration {} needs a unit such as \"{}s\" or \"{}m\"",
+                v, v, v
+            )))
+        }
+    }
+}
+
+impl de::Visitor<'_> for Visitor {
+    type Value = ConfigDuration;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a duration such as \"5m\" or \"30s\"")?;
+        if self.legacy_minutes {
+            write!(f, ", or an integer in minutes")?;
+        }
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        self.unitless(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        let v = u64::try_from(v).map_err(|_| E::custom("duration must not be negative"))?;
+        self.unitless(v)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(Visitor {
+            legacy_minutes: false,
+        })
+    }
+}
+
+pub fn deserialize_legacy_minutes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ConfigDuration, D::Error> {
+    //! 単位のない整数を分として読み込む.
+    //! 以前の設定ファイルで分の整数だった `interval` にのみ使用する
+    deserializer.deserialize_any(Visitor {
+        legacy_minutes: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        #[serde(
+            default = "default_interval",
+            deserialize_with = "deserialize_legacy_minutes"
+        )]
+        interval: ConfigDuration,
+        #[serde(default = "default_interval")]
+        cooldown: ConfigDuration,
+    }
+
+    fn default_interval() -> ConfigDuration {
+        ConfigDuration::from_mins(5)
+    }
+
+    #[test]
+    fn parse_and_display() {
+        //! 単位付きの文字列を読み込み、割り切れる最大の単位で文字列にする
+        for (s, ms) in [
+            ("5m", 300_000),
+            ("30s", 30_000),
+            ("1h30m", 5_400_000),
+            ("250ms", 250),
+        ] {
+            let duration: ConfigDuration = s.parse().unwrap();
+            assert_eq!(duration, ConfigDuration::from_millis(ms), "{}", s);
+        }
+        assert_eq!(
+            "1h30m".parse::<ConfigDuration>().unwrap().to_string(),
+            "90m"
+        );
+        assert_eq!(ConfigDuration::from_secs(0).to_string(), "0s");
+        assert!("5x".parse::<ConfigDuration>().is_err());
+        assert!("m".parse::<ConfigDuration>().is_err());
+    }
+
+    #[test]
+    fn unitless_integer_is_minutes_only_for_legacy_field() {
+        //! 単位のない整数は `deserialize_legacy_minutes` の項目でのみ分として扱い、それ以外はエラーとする
+        let config: Config = toml::from_str("interval = 3").unwrap();
+        assert_eq!(config.interval, ConfigDuration::from_mins(3));
+
+        let config: Config = toml::from_str("interval = \"30s\"\ncooldown = \"2m\"").unwrap();
+        assert_eq!(config.interval, ConfigDuration::from_secs(30));
+        assert_eq!(config.cooldown, ConfigDuration::from_mins(2));
+
+        let error = toml::from_str::<Config>("cooldown = 15").unwrap_err();
+        assert!(error.to_string().contains("needs a unit"), "{}", error);
+        assert!(toml::from_str::<Config>("interval = -1").is_err());
+    }
+}
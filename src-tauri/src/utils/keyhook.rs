@@ -1,295 +1,655 @@
 /**
- * keyboard hook for Windows
+ * keyboard input history and mistype monitor
  */
+mod keycode;
+use keycode::{KeyClass, VK_CAPITAL};
+
+mod correction;
+use correction::{CorrectionMachine, Verdict};
+
+mod detector;
+use detector::{Detectors, Evaluation, TypingMetrics};
+
+mod alert;
+use alert::AlertMachine;
+
+mod latency;
+use latency::{LatencyStats, LatencyTracker};
+
+mod ime_mode;
+use ime_mode::{ModeStats, ModeTracker, ModeVerdict};
+
+mod caps_lock;
+use caps_lock::{CapsLockStats, CapsLockTracker, CapsLockVerdict};
+
+mod hardware;
+use hardware::{HardwareTracker, KeyFault};
+
+mod activity;
+
+mod input_replay;
+pub use input_replay::ReplaySource;
+
+mod recorder;
+pub use recorder::Recorder;
+
+mod tracker;
+
+// Linux では、有効にした feature によって変換状態の通知・参照の一方が使用されない
+#[cfg_attr(not(windows), allow(dead_code))]
+mod composition;
+pub use composition::CompositionProvider;
+
+#[cfg(windows)]
+mod composition_win32;
+
+// Linux では、有効にした feature によってマウスの入力の通知が使用されない
+#[cfg_attr(not(windows), allow(dead_code))]
+mod pointer;
+pub use pointer::PointerSource;
+
+#[cfg(windows)]
+mod pointer_win32;
+
+#[cfg(all(target_os = "linux", feature = "evdev"))]
+mod pointer_evdev;
+
+#[cfg(all(target_os = "linux", feature = "x11"))]
+mod pointer_x11;
+
+#[cfg(all(target_os = "linux", feature = "dbus-ime"))]
+mod composition_dbus;
+#[cfg(all(target_os = "linux", feature = "dbus-ime"))]
+pub use composition_dbus::{DbusComposition, ImeFramework};
+
+#[cfg(windows)]
+mod input_win32;
+
+#[cfg(all(target_os = "linux", feature = "evdev"))]
+mod input_evdev;
+#[cfg(all(target_os = "linux", feature = "evdev"))]
+pub use input_evdev::EvdevSource;
+
+#[cfg(all(target_os = "linux", feature = "x11"))]
+mod input_x11;
+#[cfg(all(target_os = "linux", feature = "x11"))]
+pub use input_x11::X11Source;
+
 use crate::commands::notify;
 use crate::utils::setting;
 
 use std::{
-    collections::VecDeque,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        mpsc, Arc, Mutex, OnceLock,
-    },
+    sync::{mpsc, Mutex, OnceLock},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use core::panic;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 
-use log::{debug, error, info};
+/// キー入力の種類
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    #[default]
+    Press,   // キー押下
+    Release, // キー解放
+}
 
-use windows::Win32::{
-    Foundation::{LPARAM, LRESULT, WPARAM},
-    UI::{
-        Input::{
-            Ime::{
-                ImmGetContext, ImmGetConversionStatus, HIMC, IME_CMODE_FULLSHAPE, IME_CMODE_NATIVE,
-                IME_CONVERSION_MODE, IME_SENTENCE_MODE,
-            },
-            KeyboardAndMouse::{VK_BACK, VK_DOWN, VK_LEFT, VK_RETURN, VK_RIGHT, VK_UP},
-        },
-        WindowsAndMessaging::{
-            CallNextHookEx, GetForegroundWindow, GetMessageW, SetWindowsHookExW,
-            UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT, WH_KEYBOARD_LL, WM_KEYDOWN,
-        },
-    },
-};
+/// 入力時に押下されていた修飾キー
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    shift: bool,
+    ctrl: bool, // AltGr と同時に通知される左 Ctrl を含まない
+    alt: bool,  // AltGr (右 Alt) を含まない
+    win: bool,
+}
+
+impl Modifiers {
+    fn is_shortcut(&self) -> bool {
+        //! ショートカットキーの操作 (Ctrl, Alt, Win との同時押し) かどうかを返す.
+        //! AltGr による文字の入力は、ショートカットキーの操作に含めない.
+        self.ctrl || self.alt || self.win
+    }
+}
 
 /// キーコード記録の構造体
 #[derive(Clone, Copy)]
 pub struct Key {
-    code: u32,             // キーコード
-    ime_composition: bool, // IME変換中だったかどうか
+    code: u32,              // キーコード
+    kind: KeyKind,          // 押下か解放か
+    time: Instant,          // 入力時刻 (単調増加)
+    hold: Option<Duration>, // キーを押していた時間 (解放時のみ)
+    repeat: bool,           // OS のオートリピートによる押下かどうか
+    class: KeyClass,        // キーの分類
+    modifiers: Modifiers,   // 入力時に押下されていた修飾キー
+    ime_composition: bool,  // IME変換中だったかどうか
+    window: Option<u64>,    // 入力時にフォーカスされていたウィンドウ (取得できない場合は None)
 }
 
 impl Key {
-    pub fn new(code: u32, ime_composition: bool) -> Self {
+    pub fn new(code: u32, kind: KeyKind, ime_composition: bool) -> Self {
+        //! コンストラクタ. 入力時刻は現在時刻になる.
         Self {
             code,
+            kind,
+            time: Instant::now(),
+            hold: None,
+            repeat: false,
+            class: keycode::default_class(code),
+            modifiers: Modifiers::default(),
             ime_composition,
+            window: None,
         }
     }
-}
 
-/// キーコードの履歴を管理する構造体
-struct KeyHistory {
-    max_history_size: AtomicUsize, // キーコードの履歴の最大サイズ
-    misstype_cnt: AtomicUsize,     // ミスタイプの回数
-    history: Mutex<VecDeque<Key>>, // キーコードの履歴（スレッドセーフ）
-}
-
-fn is_ime_composition() -> bool {
-    //! 現在のIMEが変換中かどうかを bool で返す
-    //! * return `true` - IME変換中(確定前), `false` - IME変換なし、または確定済み
-    unsafe {
-        let hwnd = GetForegroundWindow();
-        if hwnd.0 == std::ptr::null_mut() {
-            return false;
-        }
+    pub fn with_time(mut self, time: Instant) -> Self {
+        //! 入力時刻を設定する
+        self.time = time;
+        self
+    }
 
-        let himc: HIMC = ImmGetContext(hwnd);
-        if himc.0 == std::ptr::null_mut() {
-            return false;
-        }
+    #[cfg(any(windows, all(target_os = "linux", feature = "x11")))]
+    pub fn with_window(mut self, window: u64) -> Self {
+        //! 入力時にフォーカスされていたウィンドウを設定する
+        self.window = Some(window);
+        self
+    }
 
-        let mut conversion: IME_CONVERSION_MODE =
-            windows::Win32::UI::Input::Ime::IME_CONVERSION_MODE(0);
-        let mut sentence: IME_SENTENCE_MODE = windows::Win32::UI::Input::Ime::IME_SENTENCE_MODE(0);
+    pub fn with_repeat(mut self, repeat: bool) -> Self {
+        //! オートリピートによる押下かどうかを設定する
+        self.repeat = repeat;
+        self
+    }
 
-        if ImmGetConversionStatus(himc, Some(&mut conversion), Some(&mut sentence)).as_bool() {
-            // IME_CMODE_NATIVE         : 日本語変換モード
-            // IME_CMODE_FULLSHAPE      : 全角モード
-            return (conversion & (IME_CMODE_NATIVE | IME_CMODE_FULLSHAPE))
-                != windows::Win32::UI::Input::Ime::IME_CONVERSION_MODE(0);
-        } else {
-            return false;
-        }
+    pub fn is_press(&self) -> bool {
+        //! キー押下かどうかを返す
+        self.kind == KeyKind::Press
     }
+
+    fn is_text(&self) -> bool {
+        //! 文字を入力するキー押下かどうかを返す.
+        //! 文字入力キーでも、ショートカットキーの操作は含めない.
+        self.class == KeyClass::Text && !self.modifiers.is_shortcut()
+    }
+}
+
+fn retract_excused(detectors: &mut Detectors, mode: ModeVerdict, caps_lock: CapsLockVerdict) {
+    //! 入力モードの間違いや Caps Lock の誤操作による削除を、ミスタイプ修正から取り消す.
+    //! 同じ削除を両方で検出した場合に二重に取り消さないよう、多い方の回数だけ取り消す.
+    let mode = match mode {
+        ModeVerdict::Mishap { corrections, .. } => corrections,
+        ModeVerdict::None => 0,
+    };
+    let caps_lock = match caps_lock {
+        CapsLockVerdict::Accident { corrections, .. } => corrections,
+        CapsLockVerdict::None => 0,
+    };
+    let corrections = mode.max(caps_lock);
+    if corrections > 0 {
+        detectors.retract(corrections);
+    }
+}
+
+/// キー入力を受け取り、ミスタイプ修正の判定と検出器への入力を行う構造体
+struct KeyHistory {
+    machine: Mutex<CorrectionMachine>, // ミスタイプ修正を判定する状態機械
+    latency: Mutex<LatencyTracker>,    // ミスタイプ修正の連続 (修正までに入力した文字数と修正の回数)
+    mode: Mutex<ModeTracker>,          // IME の入力モードの間違い
+    caps_lock: Mutex<CapsLockTracker>, // Caps Lock の誤操作
+    hardware: Mutex<HardwareTracker>,  // キーボードの故障が疑われる入力
+    detectors: Mutex<Detectors>,       // ミスタイプが多いかどうかを判定する検出器
 }
 
 impl KeyHistory {
-    pub fn new(max_history_size: usize) -> Self {
+    pub fn new() -> Self {
         //! コンストラクタ
         Self {
-            max_history_size: AtomicUsize::new(max_history_size),
-            misstype_cnt: AtomicUsize::new(0),
-            history: Mutex::new(VecDeque::with_capacity(max_history_size)),
+            machine: Mutex::new(CorrectionMachine::new()),
+            latency: Mutex::new(LatencyTracker::new()),
+            mode: Mutex::new(ModeTracker::new()),
+            caps_lock: Mutex::new(CapsLockTracker::new()),
+            hardware: Mutex::new(HardwareTracker::new()),
+            detectors: Mutex::new(Detectors::new()),
         }
     }
 
-    pub fn clear(&self) {
-        //! 履歴を全削除する
-        let mut history = self.history.lock().unwrap();
-        history.clear();
-        self.misstype_cnt.store(0, Ordering::Relaxed);
-    }
-
-    pub fn change_max_history_size(&self, max_history_size: usize) {
-        //! 履歴のサイズを変更する.
-        //! サイズが小さくなる場合、古い履歴から削除される.
-        //! 削除されるキーがBackSpaceなら、misstype_cntを減らす
+    pub fn regist_key(&self, input_key: &Key) -> Vec<MonitorEvent> {
+        //! 履歴にキー入力を記録し、ミスタイプの計算を行う.
+        //! ミスタイプの判定はキー押下の並びで行うため、キー解放は履歴に追加しない.
+        //! 修飾キーの押下状態は各キーに記録されているため、修飾キー自体も履歴に追加しない.
+        //! IME の入力モードの間違い、Caps Lock の誤操作、キーボードの故障を検出した場合は、通知するイベントとして返す.
+        let mut events = Vec::new();
 
-        self.max_history_size
-            .store(max_history_size, Ordering::Relaxed);
-        let mut history = self.history.lock().unwrap();
-        while history.len() > max_history_size {
-            let oldest = history.pop_front();
-            if let Some(old) = oldest {
-                if old.code == VK_BACK.0 as u32 && self.misstype_cnt.load(Ordering::Relaxed) > 0 {
-                    self.misstype_cnt.fetch_sub(1, Ordering::Relaxed);
-                }
-            }
+        // 故障の判定には押下と解放の間隔を使用するため、キー解放も渡す
+        if let Some(fault) = self.hardware.lock().unwrap().track(input_key) {
+            events.push(MonitorEvent::HardwareFault(fault));
         }
-    }
-
-    fn add_key(&self, key: &Key) {
-        //! 履歴にキーを追加する.
-        //! max_history_sizeを超えた場合は、最も古いキーを自動で削除する.
-        //! 削除するキーがBackSpaceなら、missype_cntを減らす.
 
-        let mut history = self.history.lock().unwrap();
-        let max_history_size = self.max_history_size.load(Ordering::Relaxed);
+        if input_key.is_press() && !input_key.repeat && input_key.code == VK_CAPITAL {
+            // Caps Lock は修飾キーとして履歴に追加しないため、ここで切り替えを記録する
+            self.caps_lock.lock().unwrap().toggle();
+        }
 
-        while history.len() >= max_history_size {
-            let oldest = history.pop_front();
-            if let Some(old) = oldest {
-                if old.code == VK_BACK.0 as u32 && self.misstype_cnt.load(Ordering::Relaxed) > 0 {
-                    self.misstype_cnt.fetch_sub(1, Ordering::Relaxed);
-                }
-            }
+        if !input_key.is_press() || input_key.class == KeyClass::Modifier {
+            return events;
+        }
+        if input_key.repeat {
+            // キーを押し続けた場合は、最初の押下だけを1回の修正として数える
+            debug!("Ignore auto-repeat: {}", input_key.code);
+            return events;
         }
-        history.push_back(key.clone());
-    }
-
-    pub fn regist_key(&self, input_key: &Key) {
-        //! 履歴にキー入力を記録し、ミスタイプの計算を行う
-        let (prev, prev_prev) = {
-            let history = self.history.lock().unwrap();
-            let len = history.len();
-            (
-                history.get(len.wrapping_sub(1)).copied(),
-                history.get(len.wrapping_sub(0)).copied(),
-            )
-        };
 
-        let vk_down = VK_DOWN.0 as u32;
-        let vk_up = VK_UP.0 as u32;
-        let vk_left = VK_LEFT.0 as u32;
-        let vk_right = VK_RIGHT.0 as u32;
-        let vk_back = VK_BACK.0 as u32;
-        let vk_enter = VK_RETURN.0 as u32;
-
-        if input_key.code == vk_back {
-            match (prev, prev_prev) {
-                (Some(prev1), Some(_prev2)) if prev1.code == vk_back => {
-                    // 連続したBackSpaceはカウントしない、履歴に追加しない
-                    debug!("Detect Continuous BackSpace");
-                }
-                (Some(prev1), Some(prev2)) if prev1.code == vk_enter && prev2.ime_composition => {
-                    // 変換中 -> Enter(変換確定) -> BackSpace の時だけミスタイプ修正とする
-                    debug!("Detect BackSpace after composition");
-                    self.misstype_cnt.fetch_add(1, Ordering::Relaxed);
-                    self.add_key(input_key);
-                }
-                (Some(prev1), Some(_prev2))
-                    if prev1.code == vk_up
-                        || prev1.code == vk_down
-                        || prev1.code == vk_right
-                        || prev1.code == vk_left =>
-                {
-                    debug!("Detect BackSpace after allow key");
-                    if setting::get_afterallow() {
-                        // 矢印キーの後をミスタイプとする設定が有効の場合のみカウント
-                        self.misstype_cnt.fetch_add(1, Ordering::Relaxed);
-                        self.add_key(input_key);
-                    }
-                }
-                _ => {
-                    // 通常のBackSpaceはミスタイプの修正にカウント
-                    debug!("Detect BackSpace");
-                    self.misstype_cnt.fetch_add(1, Ordering::Relaxed);
-                    self.add_key(input_key);
-                }
+        // 故障により余分に入力された押下は、ミスタイプ修正の判定と検出器の対象外とする
+        let verdict = if self.hardware.lock().unwrap().is_spurious() {
+            debug!("Ignore faulty key press: {}", input_key.code);
+            Verdict::Ignore
+        } else {
+            let mut machine = self.machine.lock().unwrap();
+            // マウスでのクリックや選択は、その後のキー入力の判定に使用する
+            if let Some(gesture) = pointer::take_gesture(input_key.time) {
+                machine.point(gesture);
             }
+            machine.feed(input_key, setting::get_afterallow())
+        };
+        // 故障により余分に入力された文字の削除は、ミスタイプ修正として数えない
+        let verdict = if self.hardware.lock().unwrap().absorb(input_key, verdict) {
+            Verdict::Ignore
         } else {
-            self.add_key(input_key);
+            verdict
+        };
+        let scale = self.latency.lock().unwrap().feed(input_key, verdict);
+        let weight = match verdict {
+            Verdict::Input => 0.0,
+            Verdict::Mistype(correction) => {
+                debug!("Detect {:?} (x{})", correction, scale);
+                correction.weight() * scale
+            }
+            Verdict::Ignore => {
+                debug!("Ignore key: {}", input_key.code);
+                0.0
+            }
+        };
+
+        // 入力モードの間違いや Caps Lock の誤操作による削除は、ミスタイプ修正として数えない
+        let mut detectors = self.detectors.lock().unwrap();
+        let mode_verdict = self.mode.lock().unwrap().feed(input_key, verdict);
+        if let ModeVerdict::Mishap { .. } = mode_verdict {
+            events.push(MonitorEvent::ModeMishap);
         }
+        let caps_verdict = self.caps_lock.lock().unwrap().feed(input_key, verdict);
+        if let CapsLockVerdict::Accident { .. } = caps_verdict {
+            events.push(MonitorEvent::CapsLockAccident);
+        }
+        retract_excused(&mut detectors, mode_verdict, caps_verdict);
+        detectors.feed(input_key, verdict, weight);
+        events
     }
 
-    pub fn get_recent_mistype_cnt(&self) -> usize {
-        //! 直近のミスタイプの回数を返す
-        self.misstype_cnt.load(Ordering::Relaxed)
+    pub fn flush(&self) {
+        //! 検出器の保存していない状態をファイルに保存する
+        self.detectors.lock().unwrap().flush();
     }
-}
 
-// `HHOOK` を `Send` にするためのラッパー型
-#[derive(Clone, Copy)]
-struct SafeHHook(HHOOK);
+    pub fn evaluate(&self, now: Instant) -> Vec<Evaluation> {
+        //! 選択された検出器の、時刻 `now` での評価結果を返す
+        self.detectors.lock().unwrap().evaluate(now)
+    }
 
-unsafe impl Send for SafeHHook {}
-unsafe impl Sync for SafeHHook {}
+    pub fn typing_metrics(&self, now: Instant) -> TypingMetrics {
+        //! 時刻 `now` での打鍵速度と打鍵間隔の指標を返す
+        self.detectors.lock().unwrap().typing_metrics(now)
+    }
+
+    pub fn latency_stats(&self) -> LatencyStats {
+        //! 直近のミスタイプ修正の連続の統計を返す
+        self.latency.lock().unwrap().stats()
+    }
+
+    pub fn mode_stats(&self) -> ModeStats {
+        //! IME の入力モードの間違いの統計を返す
+        self.mode.lock().unwrap().stats()
+    }
+
+    pub fn caps_lock_stats(&self) -> CapsLockStats {
+        //! Caps Lock の誤操作の統計を返す
+        self.caps_lock.lock().unwrap().stats()
+    }
+
+    pub fn hardware_faults(&self) -> Vec<KeyFault> {
+        //! キーボードの故障が疑われる入力があったキーの記録を返す
+        self.hardware.lock().unwrap().faults()
+    }
+}
 
 static HISTORY: OnceLock<KeyHistory> = OnceLock::new(); // KeyHistoryのimplがスレッドセーフとなっているので、排他処理は不要
-static HOOK: OnceLock<Arc<Mutex<Option<SafeHHook>>>> = OnceLock::new();
 static TX: OnceLock<mpsc::Sender<Key>> = OnceLock::new();
+static MONITOR_TX: OnceLock<mpsc::Sender<MonitorEvent>> = OnceLock::new();
+
+// キー入力ごとに評価する場合の、評価の最短間隔
+const EVALUATION_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// ミスタイプ率の監視スレッドを起こすイベント
+enum MonitorEvent {
+    KeyRegistered,           // キー入力を履歴に登録した
+    ConfigChanged,           // 評価に関わる設定が変更された
+    ModeMishap,              // IME の入力モードの間違いを検出した
+    CapsLockAccident,        // Caps Lock の誤操作を検出した
+    HardwareFault(KeyFault), // キーボードの故障が疑われるキーを検出した
+}
 
 const NOTIFY_TITLE: &str = "OopsTime detected a lot of mistype!";
 const NOTIFY_BODY: &str = "Shall we take a coffee break?";
+const NOTIFY_MASH_TITLE: &str = "OopsTime noticed some frantic typing";
+const NOTIFY_MASH_BODY: &str = "Mashing or hammering keys can be a sign of stress. Take a deep breath and stretch.";
+const NOTIFY_MODE_TITLE: &str = "OopsTime noticed a retyped phrase";
+const NOTIFY_MODE_BODY: &str = "Was the IME in the wrong input mode? Check half-width / hiragana before typing.";
+const NOTIFY_CAPS_LOCK_TITLE: &str = "OopsTime noticed a phrase retyped after Caps Lock";
+const NOTIFY_CAPS_LOCK_BODY: &str = "Caps Lock seems to have been pressed by accident. Check the Caps Lock light before typing.";
+const NOTIFY_HARDWARE_TITLE: &str = "OopsTime suspects a keyboard problem";
 
-fn regist_key(key: &Key) {
+fn regist_key(key: &Key) -> Vec<MonitorEvent> {
     //! グローバル変数 HISTORY のキー登録を行う関数
     let history = HISTORY.get().expect("HISTORY not initialised");
 
-    history.regist_key(key);
+    history.regist_key(key)
+}
+
+fn evaluate(now: Instant) -> Vec<Evaluation> {
+    //! グローバル変数 HISTORY の検出器の評価を行う関数
+    HISTORY.get().expect("HISTORY not initialized").evaluate(now)
 }
 
-fn get_recent_mistype_cnt() -> usize {
-    //! グローバル変数 HISTORY のミスタイプ回数の取得を行う関数
+#[tauri::command]
+pub fn get_mistype_stats() -> Vec<Evaluation> {
+    //! 選択された検出器の現在の評価結果 (ミスタイプ率と評価に使用したキー数など) を取得するメソッド.
+    //! キー入力の監視を開始していない場合は、空の配列を返す.
     HISTORY
         .get()
-        .expect("HISTORY not initialized")
-        .get_recent_mistype_cnt()
+        .map(|history| history.evaluate(Instant::now()))
+        .unwrap_or_default()
 }
 
-unsafe extern "system" fn keyboard_proc(n_code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
-    if n_code >= 0 {
-        let kb_data: &KBDLLHOOKSTRUCT = &*(l_param.0 as *const KBDLLHOOKSTRUCT);
+#[tauri::command]
+pub fn get_typing_metrics() -> TypingMetrics {
+    //! 打鍵速度 (1分あたりのキー数) と打鍵間隔の平均・標準偏差、およびセッション開始時からの変化率を取得するメソッド.
+    //! キー入力の監視を開始していない場合は、全て 0 の値を返す.
+    HISTORY
+        .get()
+        .map(|history| history.typing_metrics(Instant::now()))
+        .unwrap_or_default()
+}
 
-        if w_param == WPARAM(WM_KEYDOWN as usize) {
-            let keycode = kb_data.vkCode as u32;
-            let key = Key::new(keycode, is_ime_composition());
+#[tauri::command]
+pub fn get_correction_latency() -> LatencyStats {
+    //! 直近のミスタイプ修正の連続について、修正を始めるまでに入力した文字数と修正の回数の平均を取得するメソッド.
+    //! キー入力の監視を開始していない場合は、全て 0 の値を返す.
+    HISTORY
+        .get()
+        .map(KeyHistory::latency_stats)
+        .unwrap_or_default()
+}
 
-            if let Some(tx) = TX.get() {
-                match tx.send(key) {
-                    Ok(()) => debug!("Send key: {}", key.code),
-                    Err(mpsc::SendError(e)) => error!("Failed to send key: {}", e.code),
-                }
-            }
-        }
+#[tauri::command]
+pub fn get_mode_mishaps() -> ModeStats {
+    //! IME の入力モードの間違い (間違ったモードで入力した文字列をまとめて削除し、モードを切り替えて入力し直した) の回数を取得するメソッド.
+    //! キー入力の監視を開始していない場合は、0 回とする.
+    HISTORY
+        .get()
+        .map(KeyHistory::mode_stats)
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn get_caps_lock_accidents() -> CapsLockStats {
+    //! Caps Lock の誤操作 (Caps Lock を切り替えて入力した英字をまとめて削除し、Caps Lock を戻して入力し直した) の回数を取得するメソッド.
+    //! キー入力の監視を開始していない場合は、0 回とする.
+    HISTORY
+        .get()
+        .map(KeyHistory::caps_lock_stats)
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn get_hardware_faults() -> Vec<KeyFault> {
+    //! キーボードの故障 (スイッチのチャタリング、押していないキーの連続入力) が疑われる入力があったキーと、その回数を取得するメソッド.
+    //! キー入力の監視を開始していない場合は、空の配列を返す.
+    HISTORY
+        .get()
+        .map(KeyHistory::hardware_faults)
+        .unwrap_or_default()
+}
+
+/// キー入力を取得し、`Key` として送信する入力ソース
+pub trait InputSource: Send {
+    /// 入力ソースの名前 (ログ出力用)
+    fn name(&self) -> &'static str;
+
+    /// キー入力の取得を開始し、取得したキーを `tx` に送信する.
+    /// 入力の取得が終了するまで戻らない.
+    fn run(&mut self, tx: mpsc::Sender<Key>) -> anyhow::Result<()>;
+
+    /// 記録したキー入力を再生する入力ソースかどうか.
+    /// `true` の場合、ミスタイプ率の評価と通知の判定を、実時間ではなくキーの入力時刻に従って行う.
+    fn is_replay(&self) -> bool {
+        false
+    }
+}
+
+pub fn default_input_source() -> Option<Box<dyn InputSource>> {
+    //! 実行中のプラットフォームで使用できる入力ソースを返す.
+    //! 使用できる入力ソースがない場合は `None` を返す.
+    #[cfg(windows)]
+    {
+        Some(Box::new(input_win32::Win32Source::new()))
     }
 
-    let hook_guard = HOOK.get().unwrap().lock().unwrap();
-    if let Some(hook) = *hook_guard {
-        return CallNextHookEx(Some(hook.0), n_code, w_param, l_param);
+    #[cfg(target_os = "linux")]
+    {
+        default_linux_input_source()
     }
 
-    LRESULT(0)
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        None
+    }
 }
 
-fn set_keyboard_hook() {
-    unsafe {
-        // キーボードフック処理を登録
-        let hook: HHOOK = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), None, 0)
-            .unwrap_or_else(|_| {
-                error!("Failed to set hook");
-                panic!("Failed to set hook");
-            });
+#[cfg(target_os = "linux")]
+fn default_linux_input_source() -> Option<Box<dyn InputSource>> {
+    //! Linux では evdev を優先し、`/dev/input` を読めない場合は X11 を使用する
+    #[cfg(feature = "evdev")]
+    if EvdevSource::is_available() {
+        return Some(Box::new(EvdevSource::new()));
+    }
 
-        // `OnceLock` を初期化
-        let _ = HOOK.set(Mutex::new(Some(SafeHHook(hook))).into());
+    #[cfg(feature = "x11")]
+    if X11Source::is_available() {
+        return Some(Box::new(X11Source::new()));
+    }
 
-        let mut msg = std::mem::zeroed();
-        while GetMessageW(&mut msg, None, 0, 0).0 != 0 {
-            debug!("Received message: {}", msg.message);
-        }
+    None
+}
 
-        // フックを解除
-        if let Some(hook) = HOOK.get().unwrap().lock().unwrap().take() {
-            UnhookWindowsHookEx(hook.0).unwrap_or_else(|_| {
-                error!("Failed to unhook");
-                panic!("Failed to unhook");
-            });
+pub fn input_source_by_name(name: &str) -> Option<Box<dyn InputSource>> {
+    //! 名前を指定して入力ソースを返す.
+    //! 実行中のプラットフォームで使用できない名前の場合は `None` を返す.
+    match name {
+        #[cfg(windows)]
+        "win32" => Some(Box::new(input_win32::Win32Source::new())),
+        #[cfg(all(target_os = "linux", feature = "evdev"))]
+        "evdev" => Some(Box::new(EvdevSource::new())),
+        #[cfg(all(target_os = "linux", feature = "x11"))]
+        "x11" => Some(Box::new(X11Source::new())),
+        _ => None,
+    }
+}
+
+pub fn default_composition_provider() -> Option<Box<dyn CompositionProvider>> {
+    //! 実行中の環境で使用できる IME の変換状態の提供元を返す.
+    //! 使用できる提供元がない場合は `None` を返す.
+    #[cfg(windows)]
+    {
+        Some(Box::new(composition_win32::Win32Composition::new()))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        default_linux_composition_provider()
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_linux_composition_provider() -> Option<Box<dyn CompositionProvider>> {
+    //! Linux では、環境変数で指定されている入力メソッドフレームワークを使用する
+    #[cfg(feature = "dbus-ime")]
+    for framework in [ImeFramework::Fcitx, ImeFramework::IBus] {
+        if framework.is_running() {
+            return Some(Box::new(DbusComposition::new(framework)));
         }
     }
+
+    None
 }
 
-fn regist_key_daemon(rx: mpsc::Receiver<Key>) {
-    //! keyhookから送信したキー情報を受信し、履歴に登録する
+pub fn default_pointer_source() -> Option<Box<dyn PointerSource>> {
+    //! 実行中のプラットフォームで使用できるマウスの入力ソースを返す.
+    //! 使用できる入力ソースがない場合は `None` を返す.
+    #[cfg(windows)]
+    {
+        Some(Box::new(pointer_win32::Win32Pointer::new()))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        default_linux_pointer_source()
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_linux_pointer_source() -> Option<Box<dyn PointerSource>> {
+    //! Linux では、キー入力と同じく evdev を優先し、`/dev/input` を読めない場合は X11 を使用する
+    #[cfg(feature = "evdev")]
+    if pointer_evdev::EvdevPointer::is_available() {
+        return Some(Box::new(pointer_evdev::EvdevPointer::new()));
+    }
+
+    #[cfg(feature = "x11")]
+    if X11Source::is_available() {
+        return Some(Box::new(pointer_x11::X11Pointer::new()));
+    }
+
+    None
+}
+
+pub fn pointer_source_by_name(name: &str) -> Option<Box<dyn PointerSource>> {
+    //! 名前を指定してマウスの入力ソースを返す.
+    //! 実行中のプラットフォームで使用できない名前の場合は `None` を返す.
+    match name {
+        #[cfg(windows)]
+        "win32" => Some(Box::new(pointer_win32::Win32Pointer::new())),
+        #[cfg(all(target_os = "linux", feature = "evdev"))]
+        "evdev" => Some(Box::new(pointer_evdev::EvdevPointer::new())),
+        #[cfg(all(target_os = "linux", feature = "x11"))]
+        "x11" => Some(Box::new(pointer_x11::X11Pointer::new())),
+        _ => None,
+    }
+}
+
+pub fn composition_provider_by_name(name: &str) -> Option<Box<dyn CompositionProvider>> {
+    //! 名前を指定して IME の変換状態の提供元を返す.
+    //! 実行中のプラットフォームで使用できない名前の場合は `None` を返す.
+    match name {
+        #[cfg(windows)]
+        "win32" => Some(Box::new(composition_win32::Win32Composition::new())),
+        #[cfg(all(target_os = "linux", feature = "dbus-ime"))]
+        "ibus" => Some(Box::new(DbusComposition::new(ImeFramework::IBus))),
+        #[cfg(all(target_os = "linux", feature = "dbus-ime"))]
+        "fcitx" => Some(Box::new(DbusComposition::new(ImeFramework::Fcitx))),
+        _ => None,
+    }
+}
+
+fn composition_daemon(mut provider: Box<dyn CompositionProvider>) {
+    //! IME の変換状態の監視を行う
+    info!("Start IME composition provider: {}", provider.name());
+    match provider.run() {
+        Ok(()) => info!("Exit IME composition provider: {}", provider.name()),
+        Err(e) => error!("IME composition provider {} failed: {:#}", provider.name(), e),
+    }
+}
+
+fn pointer_daemon(mut source: Box<dyn PointerSource>) {
+    //! マウスの入力の取得を行う
+    info!("Start pointer source: {}", source.name());
+    match source.run() {
+        Ok(()) => info!("Exit pointer source: {}", source.name()),
+        Err(e) => error!("Pointer source {} failed: {:#}", source.name(), e),
+    }
+}
+
+fn input_source_daemon(mut source: Box<dyn InputSource>) {
+    //! 入力ソースからキー入力を取得し、グローバル変数 TX に送信する
+    let tx = TX.get().expect("TX not initialized").clone();
+    info!("Start input source: {}", source.name());
+    match source.run(tx) {
+        Ok(()) => info!("Exit input source: {}", source.name()),
+        Err(e) => error!("Input source {} failed: {:#}", source.name(), e),
+    }
+}
+
+fn regist_key_daemon(
+    rx: mpsc::Receiver<Key>,
+    mut recorder: Option<Recorder>,
+    mut replay: Option<Monitor>,
+) {
+    //! keyhookから送信したキー情報を受信し、履歴に登録する.
+    //! `recorder` が指定されている場合は、受信したキーを記録ファイルにも保存する.
+    //! `replay` が指定されている場合は、監視スレッドの代わりに、キーの入力時刻に従ってミスタイプ率の評価を行う.
+    let mut tracker = tracker::KeyTracker::new();
     loop {
         match rx.recv() {
             Ok(key) => {
-                debug!("Recv key: {}", key.code);
-                regist_key(&key);
+                let key = tracker.track(key, &setting::get_key_classes());
+                if let Some(monitor) = replay.as_mut() {
+                    // このキーより前に評価する時刻が来ていれば、このキーを登録する前の履歴で評価する
+                    monitor.advance(key.time);
+                }
+                activity::touch(key.time);
+                debug!("Recv key: {} {:?} hold={:?}", key.code, key.kind, key.hold);
+                if let Some(rec) = recorder.as_mut() {
+                    if let Err(e) = rec.record(&key) {
+                        // 記録に失敗しても監視は継続する
+                        error!("Failed to record key, stop recording: {:#}", e);
+                        recorder = None;
+                    }
+                }
+                let events = key
+                    .is_press()
+                    .then_some(MonitorEvent::KeyRegistered)
+                    .into_iter()
+                    .chain(regist_key(&key));
+                match replay.as_mut() {
+                    Some(monitor) => events.for_each(|event| monitor.handle(event, key.time)),
+                    None => {
+                        if let Some(tx) = MONITOR_TX.get() {
+                            // 監視スレッドが終了していても、キー履歴の処理は継続する
+                            events.for_each(|event| {
+                                let _ = tx.send(event);
+                            });
+                        }
+                    }
+                }
             }
             Err(e) => {
                 debug!("Recv error: {}", e);
@@ -297,6 +657,11 @@ fn regist_key_daemon(rx: mpsc::Receiver<Key>) {
             }
         }
     }
+    if let Some(monitor) = replay.as_mut() {
+        monitor.finish();
+    }
+    // 入力ソースが終了した場合 (記録したキー入力の再生が終わった場合など) も、学習状態を保存する
+    shutdown_keyhook();
     info!("Exit regist key daemon");
 }
 
@@ -308,47 +673,221 @@ fn make_time_str(sec: u64) -> String {
     format!("{}:{:02}:{:02}", h, m, s)
 }
 
-fn mistype_rate_monitor_daemon() {
-    //! 一定時間ごとにミスタイプ率を取得し、ミスタイプ率が閾値を超えると通知を送信する
-    loop {
-        // 設定が変更された場合でも即座に反映されるように、loopの中で値を取得する
-        let interval = setting::get_interval();
-        let threshold = setting::get_threshold();
-        let count = setting::get_count();
-        let thres_cnt = (threshold * count as f64).floor() as usize;
-        let mistype_cnt = get_recent_mistype_cnt();
+/// ミスタイプ率の評価と、通知するかどうかの判定を行う構造体.
+/// 時刻は引数で受け取るため、実時間 (監視スレッド) と、記録したキー入力の入力時刻 (再生時) のどちらでも動作する.
+/// キー入力ごと (前回の評価から `EVALUATION_DEBOUNCE` 以上の間隔を空ける) と、キー入力がなくても `interval` ごとに評価する.
+struct Monitor {
+    alert: AlertMachine,                   // ミスタイプの通知の判定
+    evaluated: Option<Instant>,            // 前回評価した時刻
+    pending: Option<Instant>,              // 前回の評価以降、最初にキーを入力した時刻
+    last_mode_alert: Option<Instant>,      // 前回 IME の入力モードの間違いを通知した時刻
+    last_caps_lock_alert: Option<Instant>, // 前回 Caps Lock の誤操作を通知した時刻
+}
+
+impl Monitor {
+    fn new() -> Self {
+        //! コンストラクタ
+        Self {
+            alert: AlertMachine::new(),
+            evaluated: None,
+            pending: None,
+            last_mode_alert: None,
+            last_caps_lock_alert: None,
+        }
+    }
+
+    fn next_evaluation(&self) -> Option<Instant> {
+        //! 次に評価する時刻を返す. 一度も評価していない場合は `None` を返す.
+        //! 設定が変更された場合でも即座に反映されるように、呼び出すたびに設定を取得する.
+        let evaluated = self.evaluated?;
+        let due = evaluated + setting::get_interval().as_duration();
+        Some(match self.pending {
+            Some(input) => due.min(input.max(evaluated + EVALUATION_DEBOUNCE)),
+            None => due,
+        })
+    }
+
+    fn evaluate(&mut self, now: Instant) {
+        //! 時刻 `now` でミスタイプ率を取得し、ミスタイプ率が閾値を超えると通知を送信する
+        self.evaluated = Some(now);
+        self.pending = None;
+        let evaluations = evaluate(now);
+        for evaluation in evaluations.iter() {
+            debug!(
+                "Current {} score: {} (limit {}, {} samples{})",
+                evaluation.name,
+                evaluation.score,
+                evaluation.limit,
+                evaluation.samples,
+                if evaluation.ready { "" } else { ", not enough" }
+            );
+        }
         debug!(
-            "Current mistype count: {},  next monitoring is {} later...",
-            mistype_cnt,
-            make_time_str(interval)
+            "Next monitoring is {} later...",
+            make_time_str(setting::get_interval().as_duration().as_secs())
         );
 
-        if mistype_cnt > thres_cnt {
-            match notify::send_notify(NOTIFY_TITLE, NOTIFY_BODY) {
+        /* 閾値を超えた状態のままでも通知を繰り返さないように、
+         * 通知するかどうかは AlertMachine で判定する */
+        if self.alert.update(&evaluations, now) {
+            let (title, body) = alert_message(&evaluations);
+            match notify::send_notify(title, body) {
                 Ok(_) => info!("Notified high mistype rate detected!"),
                 Err(err) => error!("Fail to send notify detecting hight mistype rate: {}", err),
             }
-            /* 閾値を超えた状態のままにすると、ずっと通知が送信されるので、
-             * 通知を送信した後は履歴を削除する */
-            HISTORY.get().expect("HISTORY not initialized").clear();
         }
+    }
 
-        thread::sleep(Duration::from_secs(interval));
+    fn advance(&mut self, now: Instant) {
+        //! 時刻 `now` までに評価する時刻が来ていれば、その時刻で評価する.
+        //! 一度も評価していない場合は、時刻 `now` で評価する.
+        if self.evaluated.is_none() {
+            self.evaluate(now);
+        }
+        while let Some(due) = self.next_evaluation().filter(|due| *due <= now) {
+            self.evaluate(due);
+        }
+    }
+
+    fn finish(&mut self) {
+        //! 再生の終了時に、最後のキー入力に対する評価を行う
+        if self.pending.is_some() {
+            if let Some(due) = self.next_evaluation() {
+                self.evaluate(due);
+            }
+        }
+    }
+
+    fn handle(&mut self, event: MonitorEvent, now: Instant) {
+        //! 時刻 `now` に発生したイベントを処理する.
+        //! キー入力があった場合は、評価する時刻を早める. 設定が変更された場合は、即座に評価する.
+        match event {
+            MonitorEvent::KeyRegistered => {
+                self.pending.get_or_insert(now);
+            }
+            MonitorEvent::ConfigChanged => {
+                debug!("Config changed, evaluate immediately");
+                self.evaluate(now);
+            }
+            MonitorEvent::ModeMishap => notify_hint(
+                setting::get_ime_mode().notify,
+                &mut self.last_mode_alert,
+                now,
+                NOTIFY_MODE_TITLE,
+                NOTIFY_MODE_BODY,
+            ),
+            MonitorEvent::CapsLockAccident => notify_hint(
+                setting::get_caps_lock().notify,
+                &mut self.last_caps_lock_alert,
+                now,
+                NOTIFY_CAPS_LOCK_TITLE,
+                NOTIFY_CAPS_LOCK_BODY,
+            ),
+            MonitorEvent::HardwareFault(fault) => notify_hardware_fault(&fault),
+        }
     }
 }
 
-pub fn init_keyhook() {
-    // グローバル変数 HOOK の初期化
-    HOOK.set(Arc::new(Mutex::new(Some(SafeHHook(HHOOK(
-        std::ptr::null_mut(),
-    ))))))
-    .unwrap_or_else(|_| {
-        error!("Failed to initialize SafeHHOOK structure");
-        panic!("Failed to initialize SafeHHOOK structure");
-    });
+fn alert_message(evaluations: &[Evaluation]) -> (&'static str, &'static str) {
+    //! 通知のタイトルと本文を返す.
+    //! キーの乱打や連打の検出器だけが閾値を超えた場合は、ミスタイプではなくストレスの兆候として通知する.
+    let mut exceeded = evaluations
+        .iter()
+        .filter(|evaluation| evaluation.is_exceeded(1.0))
+        .peekable();
+    if exceeded.peek().is_some() && exceeded.all(|evaluation| evaluation.name == "mash") {
+        (NOTIFY_MASH_TITLE, NOTIFY_MASH_BODY)
+    } else {
+        (NOTIFY_TITLE, NOTIFY_BODY)
+    }
+}
+
+fn mistype_rate_monitor_daemon(rx: mpsc::Receiver<MonitorEvent>) {
+    //! 実時間に従ってミスタイプ率を評価し、ミスタイプ率が閾値を超えると通知を送信する.
+    //! 次に評価する時刻まで、キー入力などのイベントを待つ.
+    let mut monitor = Monitor::new();
+    monitor.evaluate(Instant::now());
+    loop {
+        let deadline = monitor.next_evaluation().expect("evaluated at least once");
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event) => monitor.handle(event, Instant::now()),
+            Err(mpsc::RecvTimeoutError::Timeout) => monitor.evaluate(Instant::now()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                info!("Exit mistype rate monitor daemon");
+                return;
+            }
+        }
+    }
+}
 
+fn notify_hint(
+    enabled: bool,
+    last_notified: &mut Option<Instant>,
+    now: Instant,
+    title: &str,
+    body: &str,
+) {
+    //! IME の入力モードの間違いなど、特定の原因が分かる出来事を、ミスタイプの通知とは別に通知する.
+    //! 通知を繰り返さないように、前回の通知から `cooldown` 経過するまでは通知しない.
+    if !enabled {
+        return;
+    }
+    let cooldown = setting::get_alert().cooldown.as_duration();
+    if last_notified.is_some_and(|last| now.saturating_duration_since(last) < cooldown) {
+        debug!("Notification suppressed during cooldown: {}", title);
+        return;
+    }
+    *last_notified = Some(now);
+    match notify::send_notify(title, body) {
+        Ok(_) => info!("Notified: {}", title),
+        Err(err) => error!("Fail to send notify \"{}\": {}", title, err),
+    }
+}
+
+fn notify_hardware_fault(fault: &KeyFault) {
+    //! キーボードの故障が疑われるキーを、ミスタイプの通知とは別に通知する.
+    //! 通知はキーごとに、故障が疑われる入力が `notify_after` 回に達した時の1回のみ.
+    if !setting::get_hardware().notify {
+        return;
+    }
+    let symptom = if fault.chatter >= fault.phantom_repeat {
+        "registers twice per press (switch chatter)"
+    } else {
+        "repeats without being held"
+    };
+    let body = format!(
+        "The {} key {}. Its extra input is not counted as mistypes; the switch may need cleaning or replacing.",
+        fault.label(),
+        symptom
+    );
+    match notify::send_notify(NOTIFY_HARDWARE_TITLE, &body) {
+        Ok(_) => info!("Notified suspected keyboard fault: {}", fault.label()),
+        Err(err) => error!("Fail to send notify of keyboard fault: {}", err),
+    }
+}
+
+pub fn wake_monitor() {
+    //! 設定の変更を即座に反映するため、ミスタイプ率の監視スレッドを起こす.
+    //! 監視を開始していない場合と、記録したキー入力の再生中は何もしない.
+    if let Some(tx) = MONITOR_TX.get() {
+        let _ = tx.send(MonitorEvent::ConfigChanged);
+    }
+}
+
+pub fn init_keyhook(
+    source: Option<Box<dyn InputSource>>,
+    composition: Option<Box<dyn CompositionProvider>>,
+    pointer: Option<Box<dyn PointerSource>>,
+    recorder: Option<Recorder>,
+) {
+    //! キー履歴とミスタイプ率の監視を初期化し、入力ソースを起動する.
+    //! `source` が `None` の場合、キー入力は取得されない.
+    //! `composition` が `None` の場合、IME は常に変換中ではないものとして扱う.
+    //! `pointer` が `None` の場合、マウスの入力は取得されない.
+    //! `recorder` が指定されている場合、キー入力を記録ファイルに保存する.
+    //! `source` が記録したキー入力を再生する場合、ミスタイプ率の監視スレッドは起動しない.
     HISTORY
-        .set(KeyHistory::new(setting::get_count()))
+        .set(KeyHistory::new())
         .unwrap_or_else(|_| {
             error!("Failed to initialize keyboard input history");
             panic!("Failed to initialize keyboard input history");
@@ -357,30 +896,147 @@ pub fn init_keyhook() {
     let (tx, rx) = mpsc::channel::<Key>();
     TX.set(tx).expect("TX already set");
 
+    // 記録したキー入力の再生時は、再生速度に関わらず結果が同じになるように、キー履歴のスレッドで評価する
+    let replay = source.as_ref().is_some_and(|source| source.is_replay());
     thread::spawn(move || {
         // 別スレッドでキー履歴を処理する
         debug!("run regist key daemon on {:?}", thread::current().id());
-        regist_key_daemon(rx);
+        regist_key_daemon(rx, recorder, replay.then(Monitor::new));
     });
 
-    thread::spawn(|| {
-        // 別スレッドでキーフックの処理を行う
-        debug!("run set keyboard hook on {:?}", thread::current().id());
-        set_keyboard_hook();
-    });
+    match source {
+        Some(source) => {
+            thread::spawn(move || {
+                // 別スレッドで入力ソースの処理を行う
+                debug!("run input source daemon on {:?}", thread::current().id());
+                input_source_daemon(source);
+            });
+        }
+        None => warn!("No input source is available on this platform"),
+    }
+
+    match composition {
+        Some(provider) => {
+            thread::spawn(move || {
+                // 別スレッドで IME の変換状態を監視する
+                debug!("run composition daemon on {:?}", thread::current().id());
+                composition_daemon(provider);
+            });
+        }
+        None => info!("No IME composition provider is available"),
+    }
+
+    if let Some(source) = pointer {
+        thread::spawn(move || {
+            // 別スレッドでマウスの入力を取得する
+            debug!("run pointer daemon on {:?}", thread::current().id());
+            pointer_daemon(source);
+        });
+    }
 
-    thread::spawn(|| {
+    if replay {
+        // 評価はキー履歴のスレッドで行う
+        return;
+    }
+    let (monitor_tx, monitor_rx) = mpsc::channel::<MonitorEvent>();
+    MONITOR_TX.set(monitor_tx).expect("MONITOR_TX already set");
+
+    thread::spawn(move || {
         // 別スレッドミスタイプ率を監視
         debug!(
             "run mistype rate monitor daemon on {:?}",
             thread::current().id()
         );
-        mistype_rate_monitor_daemon();
+        mistype_rate_monitor_daemon(monitor_rx);
     });
 }
 
-pub fn change_max_history_size(max_history_size: usize) {
-    let history = HISTORY.get()
-        .expect("HISTORY not initialized");
-    history.change_max_history_size(max_history_size);
+pub fn shutdown_keyhook() {
+    //! アプリケーションの終了時に、検出器の保存していない状態をファイルに保存する.
+    //! キー入力の監視を開始していない場合は何もしない.
+    if let Some(history) = HISTORY.get() {
+        history.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use caps_lock::CapsLockAccident;
+    use correction::Correction;
+    use ime_mode::ModeMishap;
+    use keycode::VK_BACK;
+
+    #[test]
+    fn overlapping_mishap_and_accident_are_retracted_once() {
+        //! 同じ削除を入力モードの間違いと Caps Lock の誤操作の両方で検出した場合、
+        //! 取り消すのは多い方の回数だけで、それより前のミスタイプ修正は残ること
+        let mut detectors = Detectors::new();
+        let t0 = Instant::now();
+        let mut ms = 0;
+        let mut press = |detectors: &mut Detectors, code: u32, verdict: Verdict| {
+            let key =
+                Key::new(code, KeyKind::Press, false).with_time(t0 + Duration::from_millis(ms));
+            let weight = match verdict {
+                Verdict::Mistype(correction) => correction.weight(),
+                _ => 0.0,
+            };
+            detectors.feed(&key, verdict, weight);
+            ms += 100;
+        };
+        let backspace = Verdict::Mistype(Correction::BackSpace);
+        for _ in 0..10 {
+            press(&mut detectors, b'A' as u32, Verdict::Input);
+        }
+        for _ in 0..3 {
+            press(&mut detectors, VK_BACK, backspace);
+        }
+        for _ in 0..5 {
+            press(&mut detectors, b'A' as u32, Verdict::Input);
+        }
+        for _ in 0..5 {
+            press(&mut detectors, VK_BACK, backspace);
+        }
+
+        let mishap = ModeMishap {
+            typed: 5,
+            deleted: 5,
+        };
+        let accident = CapsLockAccident {
+            typed: 5,
+            deleted: 5,
+        };
+        retract_excused(
+            &mut detectors,
+            ModeVerdict::Mishap {
+                mishap,
+                corrections: 5,
+            },
+            CapsLockVerdict::Accident {
+                accident,
+                corrections: 5,
+            },
+        );
+        let evaluation = detectors.evaluate(t0 + Duration::from_millis(ms))[0];
+        assert_eq!(evaluation.samples, 15);
+        assert!((evaluation.score * 15.0 - 3.0 * Correction::BackSpace.weight()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mash_alert_has_its_own_message() {
+        //! キーの乱打や連打の検出器だけが閾値を超えた場合は、ストレスの兆候として通知すること
+        let evaluation = |name, score| Evaluation {
+            name,
+            score,
+            limit: 1.0,
+            samples: 100,
+            ready: true,
+        };
+        let mash = [evaluation("rate", 0.5), evaluation("mash", 2.0)];
+        assert_eq!(alert_message(&mash), (NOTIFY_MASH_TITLE, NOTIFY_MASH_BODY));
+        let mistype = [evaluation("rate", 2.0), evaluation("mash", 0.5)];
+        assert_eq!(alert_message(&mistype), (NOTIFY_TITLE, NOTIFY_BODY));
+        let both = [evaluation("rate", 2.0), evaluation("mash", 2.0)];
+        assert_eq!(alert_message(&both), (NOTIFY_TITLE, NOTIFY_BODY));
+    }
 }
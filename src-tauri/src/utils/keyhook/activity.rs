@@ -0,0 +1,58 @@
+/**
+ * user activity across the keyboard and the pointer, to tell breaks from pauses in typing
+ */
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// これより短い操作の途切れは記録しない
+const MIN_IDLE: Duration = Duration::from_secs(30);
+
+// 記録する操作の途切れの最大数
+const MAX_IDLES: usize = 64;
+
+/// キーボードとマウスの操作の記録
+struct Activity {
+    last: Option<Instant>,               // 最後に操作した時刻
+    idles: VecDeque<(Instant, Instant)>, // `MIN_IDLE` 以上操作しなかった期間 (開始と終了の時刻)
+}
+
+// 入力ソースと、マウスの入力ソースのスレッドから更新する
+static ACTIVITY: Mutex<Activity> = Mutex::new(Activity {
+    last: None,
+    idles: VecDeque::new(),
+});
+
+pub fn touch(time: Instant) {
+    //! キーボードかマウスの操作を記録する. 最後に記録した時刻より前の操作は無視する.
+    let mut activity = ACTIVITY.lock().unwrap();
+    match activity.last {
+        Some(last) if time <= last => return,
+        Some(last) if time.saturating_duration_since(last) >= MIN_IDLE => {
+            activity.idles.push_back((last, time));
+            if activity.idles.len() > MAX_IDLES {
+                activity.idles.pop_front();
+            }
+        }
+        _ => (),
+    }
+    activity.last = Some(time);
+}
+
+pub fn longest_idle(from: Instant, to: Instant) -> Duration {
+    //! `from` から `to` までの間で、キーボードとマウスのどちらも操作しなかった最長の時間を返す.
+    //! 操作を記録していない場合は、`from` から `to` までの時間を返す.
+    //! `MIN_IDLE` より短い途切れは記録しないため、それより短い値は目安となる.
+    let activity = ACTIVITY.lock().unwrap();
+    let recorded = activity
+        .idles
+        .iter()
+        .map(|(start, end)| (*end).min(to).saturating_duration_since((*start).max(from)))
+        .max()
+        .unwrap_or_default();
+    let since_last =
+        to.saturating_duration_since(activity.last.map_or(from, |last| last.max(from)));
+    recorded.max(since_last)
+}
@@ -0,0 +1,136 @@
+/**
+ * alert state machine with hysteresis and cooldown
+ */
+use super::detector::{self, Evaluation};
+use crate::utils::setting;
+
+use std::time::Instant;
+
+use log::debug;
+
+/// 通知の状態
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum AlertState {
+    #[default]
+    Armed,    // 閾値を超えると通知する
+    Disarmed, // 通知済み. 再通知の閾値を下回るまで通知しない
+}
+
+/// ミスタイプが多い状態が続いても通知を繰り返さないように、通知の可否を判定する状態機械.
+/// 通知後は、評価結果が再通知の閾値 (閾値の `rearm_ratio` 倍) を下回るまで通知しない.
+/// また、前回の通知から `cooldown` 経過するまでは通知しない.
+#[derive(Default)]
+pub struct AlertMachine {
+    state: AlertState,          // 現在の状態
+    last_alert: Option<Instant>, // 前回通知した時刻
+}
+
+impl AlertMachine {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn update(&mut self, evaluations: &[Evaluation], now: Instant) -> bool {
+        //! 検出器の評価結果を受け取って状態を遷移し、通知するかどうかを返す
+        let config = setting::get_alert();
+        match self.state {
+            AlertState::Disarmed if !detector::is_exceeded(evaluations, config.rearm_ratio) => {
+                debug!("Alert re-armed");
+                self.state = AlertState::Armed;
+            }
+            AlertState::Disarmed => return false,
+            AlertState::Armed => (),
+        }
+
+        if !detector::is_exceeded(evaluations, 1.0) {
+            return false;
+        }
+
+        let cooldown = config.cooldown.as_duration();
+        if let Some(last_alert) = self.last_alert {
+            if now.saturating_duration_since(last_alert) < cooldown {
+                debug!("Alert suppressed during cooldown");
+                return false;
+            }
+        }
+
+        self.state = AlertState::Disarmed;
+        self.last_alert = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const LIMIT: f64 = 0.1;
+
+    fn evaluations(score: f64) -> Vec<Evaluation> {
+        //! 閾値 `LIMIT` に対して `score` となる、判定に十分な入力がある評価結果を返す
+        vec![Evaluation {
+            name: "rate",
+            score,
+            limit: LIMIT,
+            samples: 100,
+            ready: true,
+        }]
+    }
+
+    fn rearm_score(offset: f64) -> f64 {
+        //! 再通知の閾値に `offset` を加えた評価値を返す
+        LIMIT * setting::get_alert().rearm_ratio + offset
+    }
+
+    fn after_cooldown(t0: Instant) -> Instant {
+        //! `t0` から、通知の間隔を空けた時刻を返す
+        t0 + setting::get_alert().cooldown.as_duration() + Duration::from_secs(1)
+    }
+
+    #[test]
+    fn fires_once_while_armed() {
+        //! 閾値を超えた最初の評価で1回だけ通知し、超えたままの間は通知しないこと
+        let mut machine = AlertMachine::new();
+        let t0 = Instant::now();
+        assert!(!machine.update(&evaluations(LIMIT / 2.0), t0));
+        assert!(machine.update(&evaluations(LIMIT * 2.0), t0));
+        assert!(!machine.update(&evaluations(LIMIT * 2.0), t0 + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn stays_silent_until_below_rearm_ratio() {
+        //! 通知後は、間隔を空けても、再通知の閾値を下回るまで通知しないこと
+        let mut machine = AlertMachine::new();
+        let t0 = Instant::now();
+        assert!(machine.update(&evaluations(LIMIT * 2.0), t0));
+        let later = after_cooldown(t0);
+        assert!(!machine.update(&evaluations(rearm_score(0.001)), later));
+        assert!(!machine.update(&evaluations(LIMIT * 2.0), later));
+    }
+
+    #[test]
+    fn rearms_below_rearm_ratio() {
+        //! 再通知の閾値を下回った後は、再び閾値を超えると通知すること
+        let mut machine = AlertMachine::new();
+        let t0 = Instant::now();
+        assert!(machine.update(&evaluations(LIMIT * 2.0), t0));
+        let later = after_cooldown(t0);
+        assert!(!machine.update(&evaluations(rearm_score(-0.001)), later));
+        assert!(machine.update(&evaluations(LIMIT * 2.0), later));
+    }
+
+    #[test]
+    fn suppressed_during_cooldown() {
+        //! 再通知の閾値を下回っても、前回の通知から間隔を空けるまでは通知しないこと
+        let mut machine = AlertMachine::new();
+        let t0 = Instant::now();
+        assert!(machine.update(&evaluations(LIMIT * 2.0), t0));
+        let soon = t0 + Duration::from_secs(1);
+        assert!(!machine.update(&evaluations(rearm_score(-0.001)), soon));
+        assert!(!machine.update(&evaluations(LIMIT * 2.0), soon));
+        // 抑制された間も再通知できる状態のままとし、間隔を空けた後に通知する
+        assert!(machine.update(&evaluations(LIMIT * 2.0), after_cooldown(t0)));
+    }
+}
@@ -0,0 +1,282 @@
+/**
+ * detection of phrases typed with Caps Lock toggled by accident
+ */
+use super::correction::Verdict;
+use super::Key;
+use crate::utils::setting;
+
+use log::{debug, info};
+use serde::Serialize;
+
+fn is_letter(key: &Key) -> bool {
+    //! Caps Lock で大文字・小文字が入れ替わるキー ('A'..='Z') かどうかを返す
+    matches!(key.code, 0x41..=0x5A)
+}
+
+/// Caps Lock の誤操作とみなした入力
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CapsLockAccident {
+    pub typed: usize,   // Caps Lock を切り替えた後に入力した英字の数
+    pub deleted: usize, // 削除した文字数
+}
+
+/// Caps Lock の誤操作の統計
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CapsLockStats {
+    pub count: usize,                   // 監視開始からの Caps Lock の誤操作の回数
+    pub last: Option<CapsLockAccident>, // 最後の Caps Lock の誤操作
+}
+
+/// 判定の結果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapsLockVerdict {
+    None, // Caps Lock の誤操作ではない
+    // Caps Lock の誤操作. 直前の `corrections` 回のミスタイプ修正は、ミスタイプとして数えない
+    Accident {
+        accident: CapsLockAccident,
+        corrections: usize,
+    },
+}
+
+/// Caps Lock を切り替えてからの入力の段階
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Phase {
+    #[default]
+    Idle,       // Caps Lock を切り替えていない
+    Typing,     // Caps Lock を切り替えた後に入力中
+    Correcting, // 入力した文字列を削除中
+}
+
+/// 「Caps Lock を有効にする → ひと続きの英字を入力 → まとめて削除 → Caps Lock を戻して入力し直す」
+/// という並びを検出する構造体.
+/// Caps Lock を戻すのは、削除の前でも後でもよい.
+/// Caps Lock の状態はキーの押下から追跡し、監視開始時は無効とみなす.
+/// 意図せず有効にした場合だけを誤操作とし、無効にしてからの入力は判定しない.
+#[derive(Default)]
+pub struct CapsLockTracker {
+    on: bool,             // Caps Lock が有効かどうか
+    phase: Phase,         // 入力の段階
+    typed: usize,         // Caps Lock を有効にした後に入力した英字の数
+    deleted: usize,       // 削除した文字数
+    corrections: usize,   // 削除の間のミスタイプ修正の回数
+    stats: CapsLockStats, // 統計
+}
+
+impl CapsLockTracker {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn stats(&self) -> CapsLockStats {
+        //! Caps Lock の誤操作の統計を返す
+        self.stats
+    }
+
+    fn reset(&mut self, phase: Phase) {
+        //! ひと続きの入力の記録を破棄する
+        self.phase = phase;
+        self.typed = 0;
+        self.deleted = 0;
+        self.corrections = 0;
+    }
+
+    pub fn toggle(&mut self) {
+        //! Caps Lock の押下を記録する. 有効にした場合は、その後の入力の記録を始める.
+        self.on = !self.on;
+        match self.phase {
+            Phase::Idle if self.on => self.reset(Phase::Typing),
+            // 何も入力しないまま戻した場合は、切り替えていないものとする
+            Phase::Typing if self.typed == 0 => self.reset(Phase::Idle),
+            Phase::Idle | Phase::Typing | Phase::Correcting => {}
+        }
+    }
+
+    pub fn feed(&mut self, key: &Key, verdict: Verdict) -> CapsLockVerdict {
+        //! キー押下と判定結果を受け取り、Caps Lock の誤操作かどうかを返す
+        match (self.phase, verdict) {
+            (Phase::Idle, _) | (_, Verdict::Ignore) => CapsLockVerdict::None,
+            (Phase::Typing, Verdict::Mistype(_)) if self.typed == 0 => {
+                self.reset(Phase::Idle);
+                CapsLockVerdict::None
+            }
+            (Phase::Typing | Phase::Correcting, Verdict::Mistype(_)) => {
+                self.phase = Phase::Correcting;
+                self.deleted += 1;
+                self.corrections += 1;
+                CapsLockVerdict::None
+            }
+            (Phase::Typing, Verdict::Input) if key.is_text() => {
+                if !self.on {
+                    // 削除せずに戻した場合は、意図した切り替えとする
+                    self.reset(Phase::Idle);
+                } else {
+                    self.typed += is_letter(key) as usize;
+                }
+                CapsLockVerdict::None
+            }
+            (Phase::Correcting, Verdict::Input) if key.is_text() => {
+                if self.on {
+                    // Caps Lock のままの入力の途中で、ミスタイプを修正しただけとする
+                    self.deleted = 0;
+                    self.corrections = 0;
+                    self.phase = Phase::Typing;
+                    self.typed += is_letter(key) as usize;
+                    return CapsLockVerdict::None;
+                }
+                let verdict = self.judge();
+                self.reset(Phase::Idle);
+                verdict
+            }
+            (_, Verdict::Input) => {
+                // Enter などで入力の区切りとなった場合は、切り替えを意図したものとする
+                self.reset(Phase::Idle);
+                CapsLockVerdict::None
+            }
+        }
+    }
+
+    fn judge(&mut self) -> CapsLockVerdict {
+        //! Caps Lock を戻して入力し直した最初のキーで、Caps Lock の誤操作かどうかを判定する
+        let config = setting::get_caps_lock();
+        if self.typed < config.min_typed || self.deleted < config.min_deleted {
+            debug!(
+                "Not a Caps Lock accident: typed {}, deleted {}",
+                self.typed, self.deleted
+            );
+            return CapsLockVerdict::None;
+        }
+
+        let accident = CapsLockAccident {
+            typed: self.typed,
+            deleted: self.deleted,
+        };
+        info!("Detect Caps Lock accident: {:?}", accident);
+        self.stats.count += 1;
+        self.stats.last = Some(accident);
+        CapsLockVerdict::Accident {
+            accident,
+            corrections: self.corrections,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::correction::Correction;
+    use crate::utils::keyhook::keycode::{VK_BACK, VK_RETURN};
+    use crate::utils::keyhook::KeyKind;
+
+    const A: u32 = b'A' as u32;
+
+    fn press(tracker: &mut CapsLockTracker, code: u32) -> CapsLockVerdict {
+        //! キー押下を渡す. BackSpace はミスタイプ修正、それ以外は入力とする
+        let key = Key::new(code, KeyKind::Press, false);
+        let verdict = match code {
+            VK_BACK => Verdict::Mistype(Correction::BackSpace),
+            _ => Verdict::Input,
+        };
+        tracker.feed(&key, verdict)
+    }
+
+    fn type_phrase(tracker: &mut CapsLockTracker, len: usize) -> Vec<CapsLockVerdict> {
+        //! 英字を `len` 文字入力する
+        (0..len).map(|_| press(tracker, A)).collect()
+    }
+
+    fn delete(tracker: &mut CapsLockTracker, len: usize) {
+        //! BackSpace で `len` 文字削除する
+        for _ in 0..len {
+            assert_eq!(press(tracker, VK_BACK), CapsLockVerdict::None);
+        }
+    }
+
+    fn accident(typed: usize, deleted: usize) -> CapsLockVerdict {
+        //! `deleted` 回の削除を取り消す Caps Lock の誤操作を返す
+        CapsLockVerdict::Accident {
+            accident: CapsLockAccident { typed, deleted },
+            corrections: deleted,
+        }
+    }
+
+    #[test]
+    fn retype_after_toggling_back_is_accident() {
+        //! Caps Lock を有効にして入力 → 削除 → Caps Lock を戻して入力し直すと、最初の文字で誤操作とする
+        let mut tracker = CapsLockTracker::new();
+        tracker.toggle();
+        assert!(type_phrase(&mut tracker, 5)
+            .iter()
+            .all(|verdict| *verdict == CapsLockVerdict::None));
+        delete(&mut tracker, 5);
+        tracker.toggle();
+        assert_eq!(press(&mut tracker, A), accident(5, 5));
+        assert_eq!(press(&mut tracker, A), CapsLockVerdict::None);
+        assert_eq!(tracker.stats().count, 1);
+    }
+
+    #[test]
+    fn toggling_back_before_deleting_is_accident() {
+        //! Caps Lock を戻してから削除した場合も、入力し直した最初の文字で誤操作とする
+        let mut tracker = CapsLockTracker::new();
+        tracker.toggle();
+        type_phrase(&mut tracker, 4);
+        tracker.toggle();
+        delete(&mut tracker, 4);
+        assert_eq!(press(&mut tracker, A), accident(4, 4));
+    }
+
+    #[test]
+    fn toggling_back_and_continuing_is_intended() {
+        //! 削除せずに Caps Lock を戻して入力を続けた場合や、途中の入力ミスを直しただけの場合は、意図した切り替えとする
+        let mut tracker = CapsLockTracker::new();
+        tracker.toggle();
+        type_phrase(&mut tracker, 4);
+        tracker.toggle();
+        assert_eq!(press(&mut tracker, A), CapsLockVerdict::None);
+
+        tracker.toggle();
+        type_phrase(&mut tracker, 4);
+        delete(&mut tracker, 1);
+        press(&mut tracker, A);
+        tracker.toggle();
+        assert!(type_phrase(&mut tracker, 5)
+            .iter()
+            .all(|verdict| *verdict == CapsLockVerdict::None));
+        assert_eq!(tracker.stats().count, 0);
+    }
+
+    #[test]
+    fn retype_after_disabling_is_not_accident() {
+        //! Caps Lock を意図して有効にした後に無効にした場合は、その後の入力を Caps Lock を戻して入力し直しても誤操作としない
+        let mut tracker = CapsLockTracker::new();
+        tracker.toggle();
+        type_phrase(&mut tracker, 5);
+        assert_eq!(press(&mut tracker, VK_RETURN), CapsLockVerdict::None);
+        tracker.toggle();
+        type_phrase(&mut tracker, 5);
+        delete(&mut tracker, 5);
+        tracker.toggle();
+        assert_eq!(press(&mut tracker, A), CapsLockVerdict::None);
+
+        // 入力を区切り、無効に戻した後は、再び有効にした場合の入力を判定する
+        press(&mut tracker, VK_RETURN);
+        tracker.toggle();
+        tracker.toggle();
+        type_phrase(&mut tracker, 5);
+        delete(&mut tracker, 5);
+        tracker.toggle();
+        assert_eq!(press(&mut tracker, A), accident(5, 5));
+    }
+
+    #[test]
+    fn short_phrase_is_not_accident() {
+        //! 入力した英字の数が `min_typed` に満たない場合は、誤操作としない
+        let mut tracker = CapsLockTracker::new();
+        tracker.toggle();
+        type_phrase(&mut tracker, 2);
+        delete(&mut tracker, 2);
+        tracker.toggle();
+        assert_eq!(press(&mut tracker, A), CapsLockVerdict::None);
+    }
+}
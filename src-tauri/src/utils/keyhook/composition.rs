@@ -0,0 +1,42 @@
+/**
+ * IME composition state shared between composition providers and input sources
+ */
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::debug;
+
+/// IME の変換中の文字列 (未確定文字列) の変化
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompositionEvent {
+    Start,  // 未確定文字列ができた
+    Commit, // 未確定文字列を確定した
+    Cancel, // 未確定文字列を確定せずに消した
+}
+
+/// IME の変換状態を監視し、変化を `notify` で通知する提供元.
+/// 入力ソースとは別のスレッドで動作し、入力ソースはキー入力時に `is_composing` で変換状態を取得する.
+pub trait CompositionProvider: Send {
+    /// 提供元の名前 (ログ出力用)
+    fn name(&self) -> &'static str;
+
+    /// 変換状態の監視を開始し、変化を `notify` で通知し続ける.
+    /// 監視を終了するか、エラーが発生すると戻る.
+    fn run(&mut self) -> anyhow::Result<()>;
+}
+
+// 未確定文字列があるかどうか. 提供元のスレッドから更新し、入力ソースのスレッドから参照する
+static COMPOSING: AtomicBool = AtomicBool::new(false);
+
+pub fn notify(event: CompositionEvent) {
+    //! 変換状態の変化を記録する
+    let composing = event == CompositionEvent::Start;
+    if COMPOSING.swap(composing, Ordering::Relaxed) != composing {
+        debug!("IME composition: {:?}", event);
+    }
+}
+
+pub fn is_composing() -> bool {
+    //! 現在、IME の未確定文字列があるかどうかを返す.
+    //! 提供元が起動していない場合は、常に `false` を返す.
+    COMPOSING.load(Ordering::Relaxed)
+}
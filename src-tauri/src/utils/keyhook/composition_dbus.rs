@@ -0,0 +1,322 @@
+/**
+ * IME composition state for Linux (IBus / Fcitx over D-Bus)
+ */
+use super::composition::{self, CompositionEvent, CompositionProvider};
+
+use anyhow::Context;
+use log::{debug, info};
+
+use zbus::{
+    blocking::{connection, Connection, MessageIterator},
+    message::{Body, Type},
+    zvariant::{OwnedValue, Value},
+};
+
+const IBUS_INPUT_CONTEXT: &str = "org.freedesktop.IBus.InputContext";
+const FCITX5_INPUT_CONTEXT: &str = "org.fcitx.Fcitx.InputContext1";
+const FCITX4_INPUT_CONTEXT: &str = "org.fcitx.Fcitx.InputContext";
+
+/// D-Bus で未確定文字列を通知する入力メソッドフレームワーク
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImeFramework {
+    IBus,  // IBus 専用のバスを使用する
+    Fcitx, // セッションバスを使用する (Fcitx 4, Fcitx 5)
+}
+
+impl ImeFramework {
+    pub fn is_running(self) -> bool {
+        //! 環境変数から、このフレームワークが使用されているかどうかを返す
+        let name = match self {
+            Self::IBus => "ibus",
+            Self::Fcitx => "fcitx",
+        };
+        ["XMODIFIERS", "GTK_IM_MODULE", "QT_IM_MODULE"]
+            .iter()
+            .filter_map(std::env::var_os)
+            .any(|value| value.to_string_lossy().contains(name))
+    }
+
+    fn match_rules(self) -> Vec<String> {
+        //! 未確定文字列の変化を通知するシグナルの match rule を返す
+        let interfaces: &[&str] = match self {
+            Self::IBus => &[IBUS_INPUT_CONTEXT],
+            Self::Fcitx => &[FCITX5_INPUT_CONTEXT, FCITX4_INPUT_CONTEXT],
+        };
+        interfaces
+            .iter()
+            .map(|interface| format!("type='signal',interface='{}'", interface))
+            .collect()
+    }
+}
+
+/// 入力コンテキストのシグナルを、未確定文字列の変化として分類したもの
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Signal {
+    Preedit(bool), // 未確定文字列が更新された (空でない場合は `true`)
+    Commit,        // 文字列が確定された
+}
+
+fn ibus_text(value: &Value) -> Option<String> {
+    //! IBusText (`(sa{sv}sv)` の構造体) から文字列を取り出す
+    match value {
+        Value::Value(inner) => ibus_text(inner),
+        Value::Structure(text) => match text.fields().get(2)? {
+            Value::Str(s) => Some(s.as_str().to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_signal(interface: &str, member: &str, body: &Body) -> zbus::Result<Option<Signal>> {
+    //! 入力コンテキストのシグナルを分類する. 未確定文字列に関係しないシグナルは `None` を返す.
+    let signal = match (interface, member) {
+        (IBUS_INPUT_CONTEXT, "UpdatePreeditText") => {
+            let (text, _cursor, visible): (OwnedValue, u32, bool) = body.deserialize()?;
+            let text = ibus_text(&text).unwrap_or_default();
+            Signal::Preedit(visible && !text.is_empty())
+        }
+        (IBUS_INPUT_CONTEXT, "UpdatePreeditTextWithMode") => {
+            let (text, _cursor, visible, _mode): (OwnedValue, u32, bool, u32) =
+                body.deserialize()?;
+            let text = ibus_text(&text).unwrap_or_default();
+            Signal::Preedit(visible && !text.is_empty())
+        }
+        (IBUS_INPUT_CONTEXT, "HidePreeditText") => Signal::Preedit(false),
+        (IBUS_INPUT_CONTEXT, "CommitText") => Signal::Commit,
+        (FCITX5_INPUT_CONTEXT | FCITX4_INPUT_CONTEXT, "UpdateFormattedPreedit") => {
+            let (preedit, _cursor): (Vec<(String, i32)>, i32) = body.deserialize()?;
+            Signal::Preedit(preedit.iter().any(|(text, _)| !text.is_empty()))
+        }
+        (FCITX5_INPUT_CONTEXT | FCITX4_INPUT_CONTEXT, "CommitString") => Signal::Commit,
+        _ => return Ok(None),
+    };
+    Ok(Some(signal))
+}
+
+/// シグナルの並びから、未確定文字列の開始・確定・取り消しを判定する構造体.
+/// 確定時は、確定のシグナルの後に空の未確定文字列が通知されるため、
+/// 確定のシグナルなしに未確定文字列が空になった場合を取り消しとする.
+#[derive(Default)]
+struct PreeditTracker {
+    composing: bool, // 未確定文字列があるかどうか
+}
+
+impl PreeditTracker {
+    fn feed(&mut self, signal: Signal) -> Option<CompositionEvent> {
+        //! シグナルを受け取り、変換状態が変化した場合はその変化を返す
+        let event = match signal {
+            Signal::Preedit(true) if !self.composing => CompositionEvent::Start,
+            Signal::Preedit(false) if self.composing => CompositionEvent::Cancel,
+            Signal::Commit if self.composing => CompositionEvent::Commit,
+            _ => return None,
+        };
+        self.composing = event == CompositionEvent::Start;
+        Some(event)
+    }
+}
+
+/// IBus または Fcitx の入力コンテキストのシグナルを D-Bus で監視し、変換状態を取得する提供元.
+/// 各アプリケーション宛てのシグナルを受信するため、バスのモニタとして接続する.
+pub struct DbusComposition {
+    framework: ImeFramework,
+    address: Option<String>, // 接続するバスのアドレス. `None` の場合はフレームワークの既定のバス
+}
+
+impl DbusComposition {
+    pub fn new(framework: ImeFramework) -> Self {
+        //! コンストラクタ
+        Self {
+            framework,
+            address: None,
+        }
+    }
+
+    pub fn with_address(framework: ImeFramework, address: &str) -> Self {
+        //! 接続するバスのアドレスを指定するコンストラクタ (テスト用のバスに接続する場合など)
+        Self {
+            framework,
+            address: Some(address.to_string()),
+        }
+    }
+
+    fn connect(&self) -> zbus::Result<Connection> {
+        //! バスに接続する
+        let builder = match (&self.address, self.framework) {
+            (Some(address), _) => connection::Builder::address(address.as_str())?,
+            (None, ImeFramework::IBus) => connection::Builder::ibus()?,
+            (None, ImeFramework::Fcitx) => connection::Builder::session()?,
+        };
+        builder.build()
+    }
+
+    fn monitor(&self, conn: &Connection) -> anyhow::Result<()> {
+        //! 入力コンテキストのシグナルを受信できるようにする.
+        //! バスが `BecomeMonitor` に対応していない場合は、`eavesdrop` の match rule で代用する.
+        let rules = self.framework.match_rules();
+        let rule_refs: Vec<&str> = rules.iter().map(String::as_str).collect();
+        let monitored = conn.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus.Monitoring"),
+            "BecomeMonitor",
+            &(rule_refs, 0u32),
+        );
+        if let Err(e) = monitored {
+            debug!("BecomeMonitor failed, fall back to eavesdropping: {}", e);
+            for rule in rules {
+                conn.call_method(
+                    Some("org.freedesktop.DBus"),
+                    "/org/freedesktop/DBus",
+                    Some("org.freedesktop.DBus"),
+                    "AddMatch",
+                    &format!("{},eavesdrop='true'", rule),
+                )
+                .context("Failed to add match rule")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CompositionProvider for DbusComposition {
+    fn name(&self) -> &'static str {
+        match self.framework {
+            ImeFramework::IBus => "ibus",
+            ImeFramework::Fcitx => "fcitx",
+        }
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        //! バスに接続し、受信したシグナルから変換状態の変化を通知し続ける
+        let conn = self
+            .connect()
+            .with_context(|| format!("Failed to connect to {} bus", self.name()))?;
+        self.monitor(&conn)?;
+        info!("Start monitoring {} input contexts", self.name());
+
+        let mut tracker = PreeditTracker::default();
+        for msg in MessageIterator::from(&conn) {
+            let msg = msg?;
+            if msg.message_type() != Type::Signal {
+                continue;
+            }
+            let header = msg.header();
+            let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+                continue;
+            };
+            match parse_signal(interface.as_str(), member.as_str(), &msg.body()) {
+                Ok(Some(signal)) => {
+                    if let Some(event) = tracker.feed(signal) {
+                        composition::notify(event);
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => debug!("Failed to parse {}.{}: {}", interface, member, e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// テスト終了時に終了する、テスト用のセッションバス
+    struct DbusDaemon {
+        child: Child,
+        address: String, // 接続するバスのアドレス
+    }
+
+    impl DbusDaemon {
+        fn start() -> Self {
+            //! 一時ディレクトリのソケットで dbus-daemon を起動し、接続できるアドレスを取得する
+            let socket =
+                std::env::temp_dir().join(format!("oopstime-test-bus-{}", std::process::id()));
+            let _ = std::fs::remove_file(&socket);
+            let mut child = Command::new("dbus-daemon")
+                .arg("--session")
+                .arg(format!("--address=unix:path={}", socket.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("Failed to start dbus-daemon");
+            let mut address = String::new();
+            BufReader::new(child.stdout.take().unwrap())
+                .read_line(&mut address)
+                .expect("Failed to read bus address");
+            Self {
+                child,
+                address: address.trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for DbusDaemon {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    fn wait_composing(expected: bool, emit: impl Fn()) -> bool {
+        //! 変換状態が `expected` になるまで、シグナルを送信し直して待つ.
+        //! 監視を開始する前に送信したシグナルは受信されないため、送信し直す.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            emit();
+            thread::sleep(Duration::from_millis(100));
+            if composition::is_composing() == expected {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    #[ignore = "requires dbus-daemon; run with `cargo test -- --ignored`"]
+    fn tracks_fcitx_preedit_on_test_bus() {
+        //! テスト用のバスに `with_address` で接続し、Fcitx 5 の未確定文字列の開始と確定を変換状態に反映すること
+        let daemon = DbusDaemon::start();
+        let address = daemon.address.clone();
+        thread::spawn(move || DbusComposition::with_address(ImeFramework::Fcitx, &address).run());
+
+        let conn = connection::Builder::address(daemon.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let path = "/org/freedesktop/portal/inputcontext/1";
+        let preedit = || {
+            let body = (vec![("nihon".to_string(), 0i32)], 5i32);
+            conn.emit_signal(
+                None::<&str>,
+                path,
+                FCITX5_INPUT_CONTEXT,
+                "UpdateFormattedPreedit",
+                &body,
+            )
+            .unwrap();
+        };
+        assert!(wait_composing(true, preedit), "Preedit was not tracked");
+
+        let commit = || {
+            conn.emit_signal(
+                None::<&str>,
+                path,
+                FCITX5_INPUT_CONTEXT,
+                "CommitString",
+                &"日本",
+            )
+            .unwrap();
+        };
+        assert!(wait_composing(false, commit), "Commit was not tracked");
+    }
+}
@@ -0,0 +1,87 @@
+/**
+ * IME composition state for Windows (WinEvent hook)
+ */
+use super::composition::{self, CompositionEvent, CompositionProvider};
+
+use anyhow::bail;
+use log::debug;
+
+use windows::Win32::{
+    Foundation::HWND,
+    UI::{
+        Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK},
+        WindowsAndMessaging::{
+            GetMessageW, EVENT_OBJECT_IME_CHANGE, EVENT_OBJECT_IME_HIDE, EVENT_OBJECT_IME_SHOW,
+            WINEVENT_OUTOFCONTEXT,
+        },
+    },
+};
+
+unsafe extern "system" fn win_event_proc(
+    _hook: HWINEVENTHOOK,
+    event: u32,
+    _hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _id_event_thread: u32,
+    _time: u32,
+) {
+    // 未確定文字列の表示・変更・非表示は、言語に依らず IME が通知する.
+    // 非表示の通知からは確定か取り消しかを区別できないため、Esc での取り消しも確定として扱う.
+    // 入力モードの間違いの検出 (`ModeTracker`) は、取り消しを Esc キーの押下で判定するため影響を受けない
+    let event = match event {
+        EVENT_OBJECT_IME_SHOW | EVENT_OBJECT_IME_CHANGE => CompositionEvent::Start,
+        EVENT_OBJECT_IME_HIDE => CompositionEvent::Commit,
+        _ => return,
+    };
+    composition::notify(event);
+}
+
+/// IME の未確定文字列の表示・非表示を `SetWinEventHook` で監視する提供元.
+/// 入力モード (ひらがな、ハングルなど) ではなく、未確定文字列があるかどうかを取得するため、
+/// 日本語以外の IME でも同じように動作する.
+#[derive(Default)]
+pub struct Win32Composition;
+
+impl Win32Composition {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self
+    }
+}
+
+impl CompositionProvider for Win32Composition {
+    fn name(&self) -> &'static str {
+        "win32"
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        //! 全プロセスの IME のイベントを監視するフックを登録し、メッセージループを回す.
+        //! メッセージループが終了すると、フックを解除して戻る.
+        unsafe {
+            let hook = SetWinEventHook(
+                EVENT_OBJECT_IME_SHOW,
+                EVENT_OBJECT_IME_CHANGE,
+                None,
+                Some(win_event_proc),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            );
+            if hook.is_invalid() {
+                bail!("Failed to set WinEvent hook");
+            }
+
+            let mut msg = std::mem::zeroed();
+            while GetMessageW(&mut msg, None, 0, 0).0 != 0 {
+                debug!("Received message: {}", msg.message);
+            }
+
+            if !UnhookWinEvent(hook).as_bool() {
+                bail!("Failed to unhook WinEvent");
+            }
+        }
+
+        Ok(())
+    }
+}
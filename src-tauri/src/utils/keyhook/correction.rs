@@ -0,0 +1,332 @@
+/**
+ * state machine to judge whether a key input is a mistype correction
+ */
+use super::keycode::{KeyClass, VK_BACK, VK_DELETE, VK_RETURN, VK_Z};
+use super::pointer::Gesture;
+use super::Key;
+use crate::utils::setting;
+
+use log::debug;
+
+/// ミスタイプ修正とみなす操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Correction {
+    BackSpace,     // BackSpace で1文字削除
+    Delete,        // Delete で1文字削除
+    WordDelete,    // Ctrl+BackSpace, Ctrl+Delete で単語削除
+    Undo,          // Ctrl+Z で元に戻す
+    SelectReplace, // Shift+カーソル移動で選択した後に、入力や削除で置き換え
+}
+
+impl Correction {
+    pub fn weight(self) -> f64 {
+        //! ミスタイプ回数として数える重みを返す
+        let weights = setting::get_correction_weights();
+        match self {
+            Self::BackSpace => weights.backspace,
+            Self::Delete => weights.delete,
+            Self::WordDelete => weights.word_delete,
+            Self::Undo => weights.undo,
+            Self::SelectReplace => weights.select_replace,
+        }
+    }
+}
+
+/// 入力中の状態
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum State {
+    #[default]
+    Typing,     // 通常の文字入力中
+    Composing,  // IME で変換中
+    Navigating, // カーソル移動中
+    Selecting,  // Shift+カーソル移動で文字列を選択中
+    Correcting, // 削除や元に戻すで修正中
+}
+
+/// 状態遷移を引き起こすキー入力の分類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Text,      // 文字入力
+    Compose,   // IME 変換中の文字入力
+    Commit,    // Enter
+    Navigate,  // カーソル移動
+    Select,    // Shift+カーソル移動
+    Erase,     // BackSpace, Delete
+    WordErase, // Ctrl+BackSpace, Ctrl+Delete
+    Undo,      // Ctrl+Z
+    Other,     // その他のキー (ショートカット, ファンクションキーなど)
+}
+
+impl Event {
+    pub fn of(key: &Key) -> Self {
+        //! キー押下を分類する
+        let modifiers = key.modifiers;
+        match key.code {
+            VK_BACK | VK_DELETE if modifiers.ctrl => Self::WordErase,
+            VK_BACK | VK_DELETE => Self::Erase,
+            VK_Z if modifiers.ctrl && !modifiers.shift && !modifiers.alt => Self::Undo,
+            _ if key.class == KeyClass::Navigation && modifiers.shift => Self::Select,
+            _ if key.class == KeyClass::Navigation => Self::Navigate,
+            VK_RETURN => Self::Commit,
+            _ if !key.is_text() => Self::Other,
+            _ if key.ime_composition => Self::Compose,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// 遷移時のキー入力の扱い
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Input,               // ミスタイプ修正ではない入力
+    Correct,             // ミスタイプ修正
+    CorrectIfAfterAllow, // 矢印キーの後をミスタイプとする設定が有効の場合のみミスタイプ修正
+    Replace,             // 選択範囲の置き換えによるミスタイプ修正
+}
+
+/// 状態遷移表の1行.
+/// `from` が `None` の行は、全ての状態に適用する.
+struct Transition {
+    from: Option<State>,
+    event: Event,
+    to: State,
+    action: Action,
+}
+
+const fn on(from: Option<State>, event: Event, to: State, action: Action) -> Transition {
+    Transition {
+        from,
+        event,
+        to,
+        action,
+    }
+}
+
+/// 状態遷移表. 上の行から順に照合し、最初に一致した行で遷移する.
+#[rustfmt::skip]
+const TRANSITIONS: &[Transition] = &[
+    // 選択範囲を入力や削除で置き換えた場合は、選択して直したものとする
+    on(Some(State::Selecting),  Event::Text,      State::Typing,     Action::Replace),
+    on(Some(State::Selecting),  Event::Compose,   State::Composing,  Action::Replace),
+    on(Some(State::Selecting),  Event::Erase,     State::Correcting, Action::Replace),
+    // カーソル移動後の削除は、入力済みの文字列の編集の場合もあるため設定に従う
+    on(Some(State::Navigating), Event::Erase,     State::Correcting, Action::CorrectIfAfterAllow),
+    // 上記以外は、直前の状態に依らず遷移する.
+    // 変換中 -> Enter(変換確定) の後は通常の入力中とし、その後の削除は確定した文字列の修正とする
+    on(None,                    Event::Text,      State::Typing,     Action::Input),
+    on(None,                    Event::Compose,   State::Composing,  Action::Input),
+    on(None,                    Event::Commit,    State::Typing,     Action::Input),
+    on(None,                    Event::Navigate,  State::Navigating, Action::Input),
+    on(None,                    Event::Select,    State::Selecting,  Action::Input),
+    on(None,                    Event::Erase,     State::Correcting, Action::Correct),
+    on(None,                    Event::WordErase, State::Correcting, Action::Correct),
+    on(None,                    Event::Undo,      State::Correcting, Action::Correct),
+    on(None,                    Event::Other,     State::Typing,     Action::Input),
+];
+
+/// キー入力の判定結果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Input,               // ミスタイプ修正ではない入力
+    Mistype(Correction), // ミスタイプ修正
+    Ignore,              // 判定の対象外 (履歴に追加しない)
+}
+
+/// キー押下の並びから、ミスタイプ修正を判定する状態機械
+#[derive(Default)]
+pub struct CorrectionMachine {
+    state: State,        // 現在の状態
+    window: Option<u64>, // 直前の入力のウィンドウ
+}
+
+impl CorrectionMachine {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn point(&mut self, gesture: Gesture) {
+        //! マウスの操作を受け取り、状態を遷移する.
+        //! マウスで選択した後の入力や削除は、Shift+カーソル移動で選択した場合と同じく、選択して直したものとする.
+        let to = match gesture {
+            Gesture::Click => State::Navigating,
+            Gesture::Select => State::Selecting,
+        };
+        debug!("{:?} --{:?}--> {:?} (pointer)", self.state, gesture, to);
+        self.state = to;
+    }
+
+    pub fn feed(&mut self, key: &Key, afterallow: bool) -> Verdict {
+        //! キー押下を1件受け取って状態を遷移し、判定結果を返す.
+        //! * `afterallow` - 矢印キーの後の削除をミスタイプとするかどうか
+
+        // フォーカスが移った場合、直前の状態は別のウィンドウへの入力なので引き継がない
+        if let (Some(prev), Some(window)) = (self.window, key.window) {
+            if prev != window {
+                self.state = State::Typing;
+            }
+        }
+        self.window = key.window.or(self.window);
+
+        let event = Event::of(key);
+        let Some(transition) = TRANSITIONS
+            .iter()
+            .find(|t| t.event == event && t.from.is_none_or(|from| from == self.state))
+        else {
+            return Verdict::Ignore;
+        };
+        debug!(
+            "{:?} --{:?}--> {:?} ({:?})",
+            self.state, event, transition.to, transition.action
+        );
+        self.state = transition.to;
+
+        let correction = match event {
+            Event::WordErase => Correction::WordDelete,
+            Event::Undo => Correction::Undo,
+            _ if key.code == VK_DELETE => Correction::Delete,
+            _ => Correction::BackSpace,
+        };
+        match transition.action {
+            Action::Input => Verdict::Input,
+            Action::Correct => Verdict::Mistype(correction),
+            Action::CorrectIfAfterAllow if afterallow => Verdict::Mistype(correction),
+            Action::CorrectIfAfterAllow => Verdict::Ignore,
+            Action::Replace => Verdict::Mistype(Correction::SelectReplace),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::keycode::{VK_F1, VK_LEFT};
+    use crate::utils::keyhook::{KeyKind, Modifiers};
+
+    /// テストで入力するキー押下
+    #[derive(Clone, Copy)]
+    enum Input {
+        Text(u8),    // 文字入力
+        Compose(u8), // IME 変換中の文字入力
+        Key(u32),    // 修飾キーなしのキー押下
+        Ctrl(u32),   // Ctrl との同時押し
+        Shift(u32),  // Shift との同時押し
+    }
+
+    fn key(input: Input) -> Key {
+        //! テスト用のキー押下を作る
+        let (code, ime, modifiers) = match input {
+            Input::Text(c) => (c as u32, false, Modifiers::default()),
+            Input::Compose(c) => (c as u32, true, Modifiers::default()),
+            Input::Key(code) => (code, false, Modifiers::default()),
+            Input::Ctrl(code) => (
+                code,
+                false,
+                Modifiers {
+                    ctrl: true,
+                    ..Modifiers::default()
+                },
+            ),
+            Input::Shift(code) => (
+                code,
+                false,
+                Modifiers {
+                    shift: true,
+                    ..Modifiers::default()
+                },
+            ),
+        };
+        let mut key = Key::new(code, KeyKind::Press, ime);
+        key.modifiers = modifiers;
+        key
+    }
+
+    fn judge(inputs: &[Input], afterallow: bool) -> Verdict {
+        //! キー押下の並びを状態機械に入力し、最後のキー押下の判定結果を返す
+        let mut machine = CorrectionMachine::new();
+        inputs
+            .iter()
+            .map(|input| machine.feed(&key(*input), afterallow))
+            .last()
+            .expect("at least one input")
+    }
+
+    use Input::{Compose, Ctrl, Key as K, Shift, Text};
+    const ON: bool = true;
+    const OFF: bool = false;
+    const BACKSPACE: Verdict = Verdict::Mistype(Correction::BackSpace);
+    const DELETE: Verdict = Verdict::Mistype(Correction::Delete);
+    const REPLACE: Verdict = Verdict::Mistype(Correction::SelectReplace);
+
+    /// 状態遷移表の各行に対応する、キー押下の並びと最後のキー押下の判定結果.
+    /// 矢印キーの後をミスタイプとする設定 (afterallow) の有効・無効で結果が変わる行は、両方を確認する.
+    #[rustfmt::skip]
+    const CASES: &[(&str, &[Input], bool, Verdict)] = &[
+        // Selecting
+        ("select -> text",              &[Shift(VK_LEFT), Text(b'A')],                 OFF, REPLACE),
+        ("select -> compose",           &[Shift(VK_LEFT), Compose(b'A')],              OFF, REPLACE),
+        ("select -> backspace",         &[Shift(VK_LEFT), K(VK_BACK)],                 OFF, REPLACE),
+        ("select -> delete",            &[Shift(VK_LEFT), K(VK_DELETE)],               OFF, REPLACE),
+        // Navigating
+        ("arrow -> backspace (on)",     &[Text(b'A'), K(VK_LEFT), K(VK_BACK)],         ON,  BACKSPACE),
+        ("arrow -> backspace (off)",    &[Text(b'A'), K(VK_LEFT), K(VK_BACK)],         OFF, Verdict::Ignore),
+        ("arrow -> delete (on)",        &[Text(b'A'), K(VK_LEFT), K(VK_DELETE)],       ON,  DELETE),
+        ("arrow -> delete (off)",       &[Text(b'A'), K(VK_LEFT), K(VK_DELETE)],       OFF, Verdict::Ignore),
+        ("arrow -> text -> bs (off)",   &[K(VK_LEFT), Text(b'A'), K(VK_BACK)],         OFF, BACKSPACE),
+        // 直前の状態に依らない行
+        ("text",                        &[Text(b'A')],                                 OFF, Verdict::Input),
+        ("compose",                     &[Compose(b'A')],                              OFF, Verdict::Input),
+        ("enter",                       &[Text(b'A'), K(VK_RETURN)],                   OFF, Verdict::Input),
+        ("compose -> enter",            &[Compose(b'A'), K(VK_RETURN)],                OFF, Verdict::Input),
+        ("compose -> enter -> bs",      &[Compose(b'A'), K(VK_RETURN), K(VK_BACK)],    OFF, BACKSPACE),
+        ("arrow",                       &[Text(b'A'), K(VK_LEFT)],                     OFF, Verdict::Input),
+        ("shift+arrow",                 &[Text(b'A'), Shift(VK_LEFT)],                 OFF, Verdict::Input),
+        ("backspace",                   &[Text(b'A'), K(VK_BACK)],                     OFF, BACKSPACE),
+        ("backspace (on)",              &[Text(b'A'), K(VK_BACK)],                     ON,  BACKSPACE),
+        ("delete",                      &[Text(b'A'), K(VK_DELETE)],                   OFF, DELETE),
+        ("backspace -> backspace",      &[Text(b'A'), K(VK_BACK), K(VK_BACK)],         OFF, BACKSPACE),
+        ("ctrl+backspace",              &[Text(b'A'), Ctrl(VK_BACK)],                  OFF, Verdict::Mistype(Correction::WordDelete)),
+        ("arrow -> ctrl+delete (off)",  &[K(VK_LEFT), Ctrl(VK_DELETE)],                OFF, Verdict::Mistype(Correction::WordDelete)),
+        ("ctrl+z",                      &[Text(b'A'), Ctrl(VK_Z)],                     OFF, Verdict::Mistype(Correction::Undo)),
+        ("ctrl+c",                      &[Text(b'A'), Ctrl(b'C' as u32)],              OFF, Verdict::Input),
+        ("function key",                &[Text(b'A'), K(VK_F1)],                       OFF, Verdict::Input),
+        ("shift+letter",                &[Shift(b'A' as u32)],                         OFF, Verdict::Input),
+        ("other -> backspace",          &[K(VK_F1), K(VK_BACK)],                       OFF, BACKSPACE),
+    ];
+
+    #[test]
+    fn sequences_to_verdicts() {
+        //! 状態遷移表の全ての行について、キー押下の並びが期待する判定結果になること
+        for (name, inputs, afterallow, expected) in CASES {
+            assert_eq!(judge(inputs, *afterallow), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn pointer_gestures_change_state() {
+        //! マウスでの選択後の入力は置き換え、クリック後の削除は矢印キーの後の削除と同じ扱いとなること
+        for (gesture, input, afterallow, expected) in [
+            (Gesture::Select, Text(b'A'), OFF, REPLACE),
+            (Gesture::Select, K(VK_BACK), OFF, REPLACE),
+            (Gesture::Click, K(VK_BACK), OFF, Verdict::Ignore),
+            (Gesture::Click, K(VK_BACK), ON, BACKSPACE),
+        ] {
+            let mut machine = CorrectionMachine::new();
+            machine.feed(&key(Text(b'A')), afterallow);
+            machine.point(gesture);
+            let verdict = machine.feed(&key(input), afterallow);
+            assert_eq!(verdict, expected, "{:?}", gesture);
+        }
+    }
+
+    #[test]
+    fn focus_change_resets_state() {
+        //! 別のウィンドウへの入力は、直前の状態を引き継がないこと
+        let (mut select, mut text) = (key(Shift(VK_LEFT)), key(Text(b'A')));
+        select.window = Some(1);
+        text.window = Some(2);
+        let mut machine = CorrectionMachine::new();
+        machine.feed(&select, OFF);
+        assert_eq!(machine.feed(&text, OFF), Verdict::Input);
+    }
+}
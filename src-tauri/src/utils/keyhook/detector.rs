@@ -0,0 +1,139 @@
+/**
+ * mistype detection strategies
+ */
+mod baseline;
+mod burst;
+mod mash;
+mod rate;
+mod rhythm;
+pub use rhythm::TypingMetrics;
+
+use super::correction::Verdict;
+use super::Key;
+use crate::utils::setting::{self, Combine, Strategy};
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// 検出器の評価結果
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub name: &'static str, // 検出器の名前
+    pub score: f64,         // 現在の値
+    pub limit: f64,         // 閾値. `score` がこれを超えるとミスタイプが多いと判定する
+    pub samples: usize,     // 評価に使用した入力の数
+    pub ready: bool,        // 判定に十分な入力があるかどうか
+}
+
+impl Evaluation {
+    pub fn is_exceeded(&self, scale: f64) -> bool {
+        //! 判定に十分な入力があり、閾値を `scale` 倍した値を超えているかどうかを返す
+        self.ready && self.score > self.limit * scale
+    }
+}
+
+pub fn is_exceeded(evaluations: &[Evaluation], scale: f64) -> bool {
+    //! 検出器の評価結果を設定に従って組み合わせ、閾値を `scale` 倍した値を超えているかどうかを返す
+    is_exceeded_by(evaluations, scale, setting::get_detector().combine)
+}
+
+fn is_exceeded_by(evaluations: &[Evaluation], scale: f64, combine: Combine) -> bool {
+    //! 検出器の評価結果を `combine` で組み合わせ、閾値を `scale` 倍した値を超えているかどうかを返す.
+    //! 評価結果が1つもない場合は、常に `false` を返す.
+    if evaluations.is_empty() {
+        return false;
+    }
+    let mut exceeded = evaluations
+        .iter()
+        .map(|evaluation| evaluation.is_exceeded(scale));
+    match combine {
+        Combine::Any => exceeded.any(|b| b),
+        Combine::All => exceeded.all(|b| b),
+    }
+}
+
+/// ミスタイプが多いかどうかを判定する検出器
+pub trait MistypeDetector: Send {
+    /// 検出器の名前 (ログ出力用)
+    fn name(&self) -> &'static str;
+
+    /// キー押下と、そのキーがミスタイプ修正かどうかの判定結果を受け取る.
+    /// `weight` は、ミスタイプ修正として数える重み (修正の連続による加重を含む. 修正でない場合は 0).
+    /// 修飾キーとオートリピートは渡されない. キーボードの故障による余分な押下は `Verdict::Ignore` として渡される.
+    fn feed(&mut self, key: &Key, verdict: Verdict, weight: f64);
+
+    /// 直近 `corrections` 回のミスタイプ修正を取り消す.
+    /// 入力モードの間違いによる削除など、後からミスタイプ修正ではないと分かった場合に使用する.
+    fn retract(&mut self, _corrections: usize) {}
+
+    /// 学習状態など、保存していない状態をファイルに保存する. 終了時に呼ばれる.
+    fn flush(&mut self) {}
+
+    /// 時刻 `now` での値と閾値を返す.
+    /// 記録したキー入力の再生時は、実時間ではなく記録時の時刻を渡す.
+    fn evaluate(&self, now: Instant) -> Evaluation;
+}
+
+/// 全ての検出器を保持し、設定で選択された検出器の評価結果を返す構造体.
+/// 設定を切り替えても直前までの入力を使えるように、選択されていない検出器にも入力を渡し続ける.
+pub struct Detectors {
+    detectors: Vec<(Strategy, Box<dyn MistypeDetector>)>,
+    rhythm: rhythm::RhythmDetector, // 打鍵のリズム (打鍵の指標の取得にも使用するため、個別に保持する)
+}
+
+impl Detectors {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self {
+            detectors: vec![
+                (Strategy::Rate, Box::new(rate::RateDetector::new())),
+                (Strategy::Burst, Box::new(burst::BurstDetector::new())),
+                (Strategy::Mash, Box::new(mash::MashDetector::new())),
+            ],
+            rhythm: rhythm::RhythmDetector::new(),
+        }
+    }
+
+    pub fn feed(&mut self, key: &Key, verdict: Verdict, weight: f64) {
+        //! 全ての検出器に入力を渡す
+        for (_, detector) in self.detectors.iter_mut() {
+            detector.feed(key, verdict, weight);
+        }
+        self.rhythm.feed(key, verdict, weight);
+    }
+
+    pub fn retract(&mut self, corrections: usize) {
+        //! 全ての検出器で、直近 `corrections` 回のミスタイプ修正を取り消す
+        for (_, detector) in self.detectors.iter_mut() {
+            detector.retract(corrections);
+        }
+        self.rhythm.retract(corrections);
+    }
+
+    pub fn flush(&mut self) {
+        //! 全ての検出器で、保存していない状態をファイルに保存する
+        for (_, detector) in self.detectors.iter_mut() {
+            detector.flush();
+        }
+        self.rhythm.flush();
+    }
+
+    pub fn evaluate(&self, now: Instant) -> Vec<Evaluation> {
+        //! 設定で選択された検出器の、時刻 `now` での評価結果を返す
+        let config = setting::get_detector();
+        let rhythm: &dyn MistypeDetector = &self.rhythm;
+        self.detectors
+            .iter()
+            .map(|(strategy, detector)| (*strategy, detector.as_ref()))
+            .chain([(Strategy::Rhythm, rhythm)])
+            .filter(|(strategy, _)| config.strategies.contains(strategy))
+            .map(|(_, detector)| detector.evaluate(now))
+            .collect()
+    }
+
+    pub fn typing_metrics(&self, now: Instant) -> TypingMetrics {
+        //! 時刻 `now` での打鍵速度と打鍵間隔の指標を返す
+        self.rhythm.metrics(now)
+    }
+}
@@ -0,0 +1,226 @@
+/**
+ * personal baseline of the mistype rate learned over time
+ */
+use crate::utils::setting;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+// 学習状態をファイルに保存する最短の間隔. 保存していない学習は終了時に保存する
+const SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+fn baseline_file_path() -> PathBuf {
+    //! 学習状態を保存するファイルのパスを返す
+    Path::new(".").join("config").join("baseline.toml")
+}
+
+/// 普段のミスタイプ率の学習状態.
+/// 一定数の文字入力ごとのミスタイプ率から、指数移動平均と指数移動分散を求める.
+/// 再起動後も学習を引き継ぐため、`SAVE_INTERVAL` ごとと終了時にファイルへ保存する.
+/// ファイルから読み込んでいない学習状態 (`Baseline::default()`) は保存しない.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Baseline {
+    mean: f64,     // ミスタイプ率の指数移動平均
+    variance: f64, // ミスタイプ率の指数移動分散
+    blocks: u64,   // 学習回数
+    #[serde(skip)]
+    path: Option<PathBuf>, // 学習状態を保存するファイルのパス
+    #[serde(skip)]
+    saved: Option<Instant>, // 最後にファイルへ保存した時刻
+    #[serde(skip)]
+    dirty: bool, // 保存していない学習があるかどうか
+}
+
+impl Baseline {
+    pub fn load() -> Self {
+        //! 学習状態を既定のファイルからロードする
+        Self::load_from(baseline_file_path())
+    }
+
+    pub fn load_from(path: PathBuf) -> Self {
+        //! 学習状態を `path` からロードし、以降の保存先とする. ファイルがない場合は学習前の状態とする.
+        let baseline = match fs::read_to_string(&path) {
+            Err(e) => {
+                debug!("No baseline loaded from {}: {}", path.display(), e);
+                Self::default()
+            }
+            Ok(baseline_str) => match toml::from_str::<Self>(&baseline_str) {
+                Err(e) => {
+                    warn!("Failed to parse baseline file, start learning again: {}", e);
+                    Self::default()
+                }
+                Ok(baseline) => {
+                    debug!("Baseline loaded successfully: {:?}", baseline);
+                    baseline
+                }
+            },
+        };
+        Self {
+            path: Some(path),
+            ..baseline
+        }
+    }
+
+    fn save(&mut self) {
+        //! 学習状態をファイルに保存する
+        self.dirty = false;
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                error!("Failed to create config directory: {}", e);
+            }
+        }
+
+        match toml::to_string_pretty(self) {
+            Err(_) => error!("Failed to serialize baseline"),
+            Ok(baseline_str) => {
+                if let Err(e) = fs::write(path, baseline_str) {
+                    error!("Failed to save baseline file: {}", e);
+                }
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        //! 保存していない学習があれば、ファイルに保存する
+        if self.dirty {
+            self.save();
+        }
+    }
+
+    pub fn learn(&mut self, rate: f64, now: Instant) {
+        //! 時刻 `now` までの一定数の文字入力でのミスタイプ率を1件学習する.
+        //! 前回の保存から `SAVE_INTERVAL` 以上経過していれば、ファイルに保存する.
+        //! 疲れなどでミスタイプが多い期間に普段の値が引き上げられないように、
+        //! 学習済みの場合は閾値を超えた分を切り捨てて学習する.
+        let config = setting::get_baseline();
+        let rate = self.limit().map_or(rate, |limit| rate.min(limit));
+
+        if self.blocks == 0 {
+            self.mean = rate;
+            self.variance = 0.0;
+        } else {
+            let diff = rate - self.mean;
+            self.mean += config.alpha * diff;
+            self.variance = (1.0 - config.alpha) * (self.variance + config.alpha * diff * diff);
+        }
+        self.blocks += 1;
+        debug!(
+            "Baseline learned {}: mean {}, stddev {} ({} blocks)",
+            rate,
+            self.mean,
+            self.variance.sqrt(),
+            self.blocks
+        );
+
+        self.dirty = true;
+        if self
+            .saved
+            .is_none_or(|saved| now.saturating_duration_since(saved) >= SAVE_INTERVAL)
+        {
+            self.saved = Some(now);
+            self.save();
+        }
+    }
+
+    pub fn limit(&self) -> Option<f64> {
+        //! 普段の値から、ミスタイプが多いと判定する閾値を返す.
+        //! 学習回数が `min_blocks` に満たない場合は `None` を返す.
+        let config = setting::get_baseline();
+        if self.blocks < config.min_blocks.max(1) {
+            return None;
+        }
+        let margin = (config.sigma * self.variance.sqrt()).max(config.min_margin);
+        Some(self.mean + margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn learned(rates: &[f64], t0: Instant) -> Baseline {
+        //! `rates` を1秒ごとに学習した、保存しない学習状態を返す
+        let mut baseline = Baseline::default();
+        for (i, rate) in rates.iter().enumerate() {
+            baseline.learn(*rate, t0 + Duration::from_secs(i as u64));
+        }
+        baseline
+    }
+
+    #[test]
+    fn limit_requires_min_blocks() {
+        //! 学習回数が `min_blocks` に達するまでは閾値を返さないこと
+        let min_blocks = setting::get_baseline().min_blocks as usize;
+        let t0 = Instant::now();
+        assert_eq!(learned(&vec![0.05; min_blocks - 1], t0).limit(), None);
+        assert!(learned(&vec![0.05; min_blocks], t0).limit().is_some());
+    }
+
+    #[test]
+    fn limit_follows_deviation() {
+        //! 閾値は普段の値から標準偏差の `sigma` 倍離れた値とし、ばらつきが小さい場合は `min_margin` だけ離すこと
+        let config = setting::get_baseline();
+        let t0 = Instant::now();
+        let steady = learned(&vec![0.05; 40], t0);
+        let limit = steady.limit().unwrap();
+        assert!(
+            (limit - (0.05 + config.min_margin)).abs() < 1e-9,
+            "{}",
+            limit
+        );
+
+        let rates: Vec<f64> = (0..40).map(|i| [0.0, 0.1][i % 2]).collect();
+        let noisy = learned(&rates, t0);
+        let expected = noisy.mean + config.sigma * noisy.variance.sqrt();
+        assert!(noisy.variance.sqrt() * config.sigma > config.min_margin);
+        assert!((noisy.limit().unwrap() - expected).abs() < 1e-9);
+        assert!(noisy.limit().unwrap() > limit);
+    }
+
+    #[test]
+    fn excess_is_clipped_to_limit() {
+        //! 学習済みの場合、閾値を超えたミスタイプ率は閾値として学習すること
+        let t0 = Instant::now();
+        let mut baseline = learned(&vec![0.05; 40], t0);
+        let limit = baseline.limit().unwrap();
+        let mut clipped = baseline.clone();
+        baseline.learn(0.9, t0 + Duration::from_secs(60));
+        clipped.learn(limit, t0 + Duration::from_secs(60));
+        assert_eq!(baseline.mean, clipped.mean);
+        assert_eq!(baseline.variance, clipped.variance);
+    }
+
+    #[test]
+    fn persists_across_restart() {
+        //! 最初の学習と `SAVE_INTERVAL` ごとにファイルへ保存し、残りは `flush` で保存すること
+        let path = std::env::temp_dir().join(format!("baseline-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let t0 = Instant::now();
+
+        let mut baseline = Baseline::load_from(path.clone());
+        assert_eq!(baseline.blocks, 0);
+        baseline.learn(0.04, t0);
+        baseline.learn(0.06, t0 + Duration::from_secs(1));
+        assert_eq!(Baseline::load_from(path.clone()).blocks, 1);
+
+        baseline.learn(0.05, t0 + SAVE_INTERVAL);
+        assert_eq!(Baseline::load_from(path.clone()).blocks, 3);
+
+        baseline.learn(0.05, t0 + SAVE_INTERVAL + Duration::from_secs(1));
+        baseline.flush();
+        let restored = Baseline::load_from(path.clone());
+        assert_eq!(restored.blocks, 4);
+        assert_eq!(restored.mean, baseline.mean);
+        assert_eq!(restored.variance, baseline.variance);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+/**
+ * mistype detector by corrections concentrated in a short time
+ */
+use super::{Evaluation, MistypeDetector};
+use crate::utils::keyhook::{correction::Verdict, Key};
+use crate::utils::setting;
+
+use std::{collections::VecDeque, time::Instant};
+
+/// 短時間にミスタイプ修正が集中した場合に判定する検出器.
+/// 直近 `burst_window` のミスタイプ修正の重みの合計が `burst_count` を超えると判定する.
+#[derive(Default)]
+pub struct BurstDetector {
+    corrections: VecDeque<(Instant, f64)>, // ミスタイプ修正の入力時刻と重み
+    latest: Option<Instant>,               // 最後に入力した時刻
+}
+
+impl BurstDetector {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+}
+
+impl MistypeDetector for BurstDetector {
+    fn name(&self) -> &'static str {
+        "burst"
+    }
+
+    fn feed(&mut self, key: &Key, verdict: Verdict, weight: f64) {
+        //! ミスタイプ修正を記録し、時間窓より古い修正を削除する
+        if verdict == Verdict::Ignore {
+            return;
+        }
+        self.latest = Some(key.time);
+        if let Verdict::Mistype(_) = verdict {
+            self.corrections.push_back((key.time, weight));
+        }
+
+        let window = setting::get_detector().burst_window.as_duration();
+        while self
+            .corrections
+            .front()
+            .is_some_and(|(time, _)| key.time.saturating_duration_since(*time) > window)
+        {
+            self.corrections.pop_front();
+        }
+    }
+
+    fn retract(&mut self, corrections: usize) {
+        //! 直近のミスタイプ修正を削除する
+        let len = self.corrections.len();
+        self.corrections.truncate(len.saturating_sub(corrections));
+    }
+
+    fn evaluate(&self, _now: Instant) -> Evaluation {
+        //! 最後の入力から遡って時間窓内のミスタイプ修正の重みの合計と、閾値を返す
+        let config = setting::get_detector();
+        let window = config.burst_window.as_duration();
+        let (score, samples) = match self.latest {
+            Some(latest) => self
+                .corrections
+                .iter()
+                .filter(|(time, _)| latest.saturating_duration_since(*time) <= window)
+                .fold((0.0, 0), |(score, cnt), (_, weight)| (score + weight, cnt + 1)),
+            None => (0.0, 0),
+        };
+        Evaluation {
+            name: self.name(),
+            score,
+            limit: config.burst_count as f64,
+            samples,
+            ready: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::is_exceeded_by;
+    use super::*;
+    use crate::utils::keyhook::correction::Correction;
+    use crate::utils::keyhook::keycode::VK_BACK;
+    use crate::utils::keyhook::KeyKind;
+    use crate::utils::setting::Combine;
+
+    use std::time::Duration;
+
+    /// 一定の時刻から始まるキー入力を検出器に渡す
+    struct Timeline {
+        detector: BurstDetector,
+        started: Instant,
+    }
+
+    impl Timeline {
+        fn new() -> Self {
+            Self {
+                detector: BurstDetector::new(),
+                started: Instant::now(),
+            }
+        }
+
+        fn correct(&mut self, ms: u64) {
+            //! 開始から `ms` ミリ秒後に BackSpace でミスタイプ修正をする
+            let time = self.started + Duration::from_millis(ms);
+            let key = Key::new(VK_BACK, KeyKind::Press, false).with_time(time);
+            let correction = Correction::BackSpace;
+            self.detector
+                .feed(&key, Verdict::Mistype(correction), correction.weight());
+        }
+
+        fn type_text(&mut self, ms: u64) {
+            //! 開始から `ms` ミリ秒後に文字を入力する
+            let time = self.started + Duration::from_millis(ms);
+            let key = Key::new(b'A' as u32, KeyKind::Press, false).with_time(time);
+            self.detector.feed(&key, Verdict::Input, 0.0);
+        }
+
+        fn evaluate(&self) -> Evaluation {
+            //! 最後の入力から遡って評価する
+            self.detector.evaluate(self.started)
+        }
+    }
+
+    fn rate(score: f64) -> Evaluation {
+        //! 閾値 0.1 に対して `score` となる、判定に十分な入力があるミスタイプ率の評価結果を返す
+        Evaluation {
+            name: "rate",
+            score,
+            limit: 0.1,
+            samples: 100,
+            ready: true,
+        }
+    }
+
+    #[test]
+    fn burst_within_window_is_exceeded() {
+        //! 時間窓内のミスタイプ修正の重みの合計が `burst_count` を超えると判定すること
+        let burst_count = setting::get_detector().burst_count as u64;
+        let mut timeline = Timeline::new();
+        for i in 0..burst_count {
+            timeline.correct(i * 500);
+        }
+        assert!(!timeline.evaluate().is_exceeded(1.0));
+        timeline.correct(burst_count * 500);
+        let evaluation = timeline.evaluate();
+        assert_eq!(evaluation.samples, burst_count as usize + 1);
+        assert!(evaluation.is_exceeded(1.0), "{:?}", evaluation);
+    }
+
+    #[test]
+    fn burst_falls_out_of_window() {
+        //! 時間窓より前のミスタイプ修正は、文字入力を続けると数えなくなること
+        let config = setting::get_detector();
+        let window = config.burst_window.as_duration().as_millis() as u64;
+        let mut timeline = Timeline::new();
+        for i in 0..=config.burst_count as u64 {
+            timeline.correct(i * 100);
+        }
+        assert!(timeline.evaluate().is_exceeded(1.0));
+
+        timeline.type_text(window * 2);
+        let evaluation = timeline.evaluate();
+        assert!(!evaluation.is_exceeded(1.0), "{:?}", evaluation);
+        assert_eq!(evaluation.samples, 0);
+
+        // 時間窓より前の修正は、再び修正が集中しても数えない
+        timeline.correct(window * 3);
+        assert_eq!(timeline.evaluate().samples, 1);
+        assert_eq!(timeline.detector.corrections.len(), 1);
+    }
+
+    #[test]
+    fn combined_with_rate() {
+        //! ミスタイプ率と組み合わせた場合、`any` はいずれか、`all` は両方が閾値を超えると判定すること
+        let burst_count = setting::get_detector().burst_count as u64;
+        let mut timeline = Timeline::new();
+        for i in 0..=burst_count {
+            timeline.correct(i * 100);
+        }
+        let burst = timeline.evaluate();
+
+        let mut calm = Timeline::new();
+        calm.correct(0);
+        let calm = calm.evaluate();
+
+        for (evaluations, any, all) in [
+            ([rate(0.05), burst], true, false),
+            ([rate(0.2), calm], true, false),
+            ([rate(0.2), burst], true, true),
+            ([rate(0.05), calm], false, false),
+        ] {
+            assert_eq!(
+                is_exceeded_by(&evaluations, 1.0, Combine::Any),
+                any,
+                "{:?}",
+                evaluations
+            );
+            assert_eq!(
+                is_exceeded_by(&evaluations, 1.0, Combine::All),
+                all,
+                "{:?}",
+                evaluations
+            );
+        }
+        // 判定に十分な入力がない検出器は、閾値を超えていないものとする
+        let not_ready = Evaluation {
+            ready: false,
+            ..rate(0.2)
+        };
+        assert!(!is_exceeded_by(&[not_ready, burst], 1.0, Combine::All));
+    }
+}
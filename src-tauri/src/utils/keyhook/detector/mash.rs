@@ -0,0 +1,206 @@
+/**
+ * stress detector by key mashing and hammering of the same key
+ */
+use super::{Evaluation, MistypeDetector};
+use crate::utils::keyhook::{correction::Verdict, keycode::VK_SPACE, Key};
+use crate::utils::setting::{self, DetectorConfig};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+
+use log::debug;
+
+fn entropy(codes: impl Iterator<Item = u32>) -> f64 {
+    //! キーコードの分布のエントロピー (bit) を返す
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let mut total = 0;
+    for code in codes {
+        *counts.entry(code).or_default() += 1;
+        total += 1;
+    }
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// 同じ文字入力のキーの連打
+#[derive(Clone, Copy)]
+struct Hammer {
+    code: u32,     // キーコード
+    count: usize,  // 連続した押下の回数
+    last: Instant, // 最後の押下の時刻
+}
+
+/// キーの乱打 (多くのキーを非常に速く押す) と、同じキーの連打を、ストレスの兆候として判定する検出器.
+/// ミスタイプ修正の割合とは別に、直近 `mash_window` の乱打と連打の回数が `mash_count` を超えると判定する.
+#[derive(Default)]
+pub struct MashDetector {
+    recent: VecDeque<(Instant, u32)>, // 直近 `mash_keys` 回の押下の時刻とキーコード
+    mashing: bool,                    // 乱打中かどうか (1回の乱打を重複して数えないため)
+    hammer: Option<Hammer>,           // 連打中のキー
+    events: VecDeque<Instant>,        // 乱打と連打を検出した時刻
+    latest: Option<Instant>,          // 最後に入力した時刻
+}
+
+impl MashDetector {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    fn is_mashing(&self, config: &DetectorConfig) -> bool {
+        //! 直近 `mash_keys` 回の押下が、平均間隔 `mash_interval` 未満で、多くのキーにばらついているかどうかを返す
+        let (Some((first, _)), Some((last, _))) = (self.recent.front(), self.recent.back()) else {
+            return false;
+        };
+        if self.recent.len() < config.mash_keys.max(2) {
+            return false;
+        }
+        let mean = last.saturating_duration_since(*first) / (self.recent.len() - 1) as u32;
+        mean < config.mash_interval.as_duration()
+            && entropy(self.recent.iter().map(|(_, code)| *code)) >= config.mash_entropy
+    }
+}
+
+impl MistypeDetector for MashDetector {
+    fn name(&self) -> &'static str {
+        "mash"
+    }
+
+    fn feed(&mut self, key: &Key, verdict: Verdict, _weight: f64) {
+        //! 押下を記録し、乱打と連打を検出する.
+        //! BackSpace で単語を消す、矢印キーでカーソルを動かす、Enter で改行する、Space で変換候補を選ぶといった操作は
+        //! 同じキーを素早く押すのが普通なので、Space 以外の文字入力のキーの押下のみを連打として数える.
+        //! キーボードの故障による押下など、判定の対象外の押下は数えない.
+        if verdict == Verdict::Ignore {
+            return;
+        }
+        let config = setting::get_detector();
+        self.latest = Some(key.time);
+
+        self.recent.push_back((key.time, key.code));
+        while self.recent.len() > config.mash_keys.max(2) {
+            self.recent.pop_front();
+        }
+        let mashing = self.is_mashing(&config);
+        if mashing && !self.mashing {
+            debug!("Detect key mashing");
+            self.events.push_back(key.time);
+        }
+        self.mashing = mashing;
+
+        let interval = config.hammer_interval.as_duration();
+        let hammer = match self.hammer {
+            _ if !key.is_text() || key.code == VK_SPACE => None,
+            Some(hammer)
+                if hammer.code == key.code
+                    && key.time.saturating_duration_since(hammer.last) < interval =>
+            {
+                Some(Hammer {
+                    count: hammer.count + 1,
+                    last: key.time,
+                    ..hammer
+                })
+            }
+            _ => Some(Hammer {
+                code: key.code,
+                count: 1,
+                last: key.time,
+            }),
+        };
+        if hammer.is_some_and(|hammer| hammer.count == config.hammer_count.max(2)) {
+            debug!("Detect hammering of key {}", key.code);
+            self.events.push_back(key.time);
+        }
+        self.hammer = hammer;
+
+        let window = config.mash_window.as_duration();
+        while self
+            .events
+            .front()
+            .is_some_and(|time| key.time.saturating_duration_since(*time) > window)
+        {
+            self.events.pop_front();
+        }
+    }
+
+    fn evaluate(&self, _now: Instant) -> Evaluation {
+        //! 最後の入力から遡って時間窓内の乱打と連打の回数と、閾値を返す
+        let config = setting::get_detector();
+        let window = config.mash_window.as_duration();
+        let score = match self.latest {
+            Some(latest) => self
+                .events
+                .iter()
+                .filter(|time| latest.saturating_duration_since(**time) <= window)
+                .count(),
+            None => 0,
+        };
+        Evaluation {
+            name: self.name(),
+            score: score as f64,
+            limit: config.mash_count as f64,
+            samples: score,
+            ready: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::correction::Correction;
+    use crate::utils::keyhook::keycode::{VK_BACK, VK_LEFT, VK_RETURN};
+    use crate::utils::keyhook::KeyKind;
+    use std::time::Duration;
+
+    fn hammer(code: u32, verdict: Verdict) -> f64 {
+        //! 同じキーを 100ms 間隔で 10 回押した場合の、乱打と連打の回数を返す
+        let mut detector = MashDetector::new();
+        let started = Instant::now();
+        for i in 0..10 {
+            let key = Key::new(code, KeyKind::Press, false)
+                .with_time(started + Duration::from_millis(i * 100));
+            detector.feed(&key, verdict, 0.0);
+        }
+        detector.evaluate(started).score
+    }
+
+    #[test]
+    fn text_key_hammering_is_counted() {
+        //! 文字入力のキーの連打は、ストレスの兆候として数える
+        assert_eq!(hammer(b'A' as u32, Verdict::Input), 1.0);
+    }
+
+    #[test]
+    fn other_key_hammering_is_not_counted() {
+        //! 削除、カーソル移動、改行、空白のキーの連打は数えない
+        assert_eq!(
+            hammer(VK_BACK, Verdict::Mistype(Correction::BackSpace)),
+            0.0
+        );
+        assert_eq!(hammer(VK_BACK, Verdict::Ignore), 0.0);
+        assert_eq!(hammer(VK_LEFT, Verdict::Input), 0.0);
+        assert_eq!(hammer(VK_RETURN, Verdict::Input), 0.0);
+        assert_eq!(hammer(VK_SPACE, Verdict::Input), 0.0);
+    }
+
+    #[test]
+    fn mashing_is_counted_once() {
+        //! 多くのキーを非常に速く押した場合は、1回の乱打として数える
+        let mut detector = MashDetector::new();
+        let started = Instant::now();
+        for (i, code) in b"QWERTYUIOPASDF".iter().enumerate() {
+            let key = Key::new(*code as u32, KeyKind::Press, false)
+                .with_time(started + Duration::from_millis(i as u64 * 20));
+            detector.feed(&key, Verdict::Input, 0.0);
+        }
+        assert_eq!(detector.evaluate(started).score, 1.0);
+    }
+}
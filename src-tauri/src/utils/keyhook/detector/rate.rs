@@ -0,0 +1,366 @@
+/**
+ * mistype detector by the ratio of corrections to key inputs
+ */
+use super::baseline::Baseline;
+use super::{Evaluation, MistypeDetector};
+use crate::utils::keyhook::{correction::Verdict, Key};
+use crate::utils::setting::{self, RateWindow, ThresholdMode};
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// 時間窓でミスタイプ率を計算する場合に、入力の途切れを打鍵時間として数える上限.
+// これより長い間隔は休憩などで入力していなかったものとし、この時間だけ経過したとみなす.
+const IDLE_GAP: Duration = Duration::from_secs(30);
+
+// 履歴の最大サイズ (メモリ使用量の上限)
+const MAX_HISTORY_SIZE: usize = 100_000;
+
+/// キーコードの履歴の1件分
+#[derive(Clone, Copy)]
+struct HistoryEntry {
+    time: Instant,    // 入力時刻
+    active: Duration, // 履歴の先頭からの打鍵時間の累計 (入力の途切れは IDLE_GAP までとする)
+    weight: f64,      // ミスタイプ修正としての重み (修正でない場合は 0)
+    text: bool,       // 文字を入力するキーかどうか
+}
+
+/// 直近の文字入力に対するミスタイプ修正の割合 (修正の重みの合計 / 文字入力のキー数) で判定する検出器.
+/// カーソル移動などを多用しても割合が薄まらないように、分母には文字を入力するキーのみを数える.
+/// 範囲は設定の `window` に従い、直近 `count` 回の文字入力、または直近 `window_length` の打鍵時間とする.
+/// 閾値は、`count` 回の文字入力ごとのミスタイプ率から学習した普段の値を元に決める.
+pub struct RateDetector {
+    history: VecDeque<HistoryEntry>, // キーコードの履歴
+    text_cnt: usize,                 // 履歴中の文字入力のキー数
+    baseline: Baseline,              // 普段のミスタイプ率の学習状態
+    block_keys: usize,               // 学習前の区間のキー数
+    block_weight: f64,               // 学習前の区間のミスタイプ修正の重みの合計
+    block_text: usize,               // 学習前の区間の文字入力のキー数
+}
+
+impl RateDetector {
+    pub fn new() -> Self {
+        //! コンストラクタ. 前回までの学習状態をファイルから読み込む.
+        Self::with_baseline(Baseline::load())
+    }
+
+    fn with_baseline(baseline: Baseline) -> Self {
+        //! 学習状態を指定するコンストラクタ
+        Self {
+            history: VecDeque::new(),
+            text_cnt: 0,
+            baseline,
+            block_keys: 0,
+            block_weight: 0.0,
+            block_text: 0,
+        }
+    }
+
+    fn learn(&mut self, weight: f64, text: bool, now: Instant) {
+        //! 区間にキーを追加し、`count` 回の文字入力ごとに、区間のミスタイプ率を学習する
+        self.block_keys += 1;
+        self.block_weight += weight;
+        self.block_text += text as usize;
+        if self.block_text >= setting::get_count().max(1) {
+            self.baseline
+                .learn(self.block_weight / self.block_text as f64, now);
+            self.block_keys = 0;
+            self.block_weight = 0.0;
+            self.block_text = 0;
+        }
+    }
+
+    fn limit(&self) -> f64 {
+        //! 設定に従って閾値を返す. 学習が足りない場合は、設定の `threshold` を閾値とする.
+        let threshold = setting::get_threshold();
+        match setting::get_baseline().mode {
+            ThresholdMode::Fixed => threshold,
+            ThresholdMode::Baseline => self.baseline.limit().unwrap_or(threshold),
+        }
+    }
+
+    fn window_length() -> Duration {
+        //! 時間窓の長さを返す
+        setting::get_window_length().as_duration()
+    }
+
+    fn recent(&self, window: RateWindow, now: Instant) -> impl Iterator<Item = &HistoryEntry> {
+        //! 時刻 `now` で、範囲 `window` 内の履歴を返す.
+        //! 時間窓で計算する場合、最後の入力から時間窓以上経過していれば、古い入力のみのため空とする.
+        let len = self.history.len();
+        let skip = match window {
+            RateWindow::Count => {
+                let count = setting::get_count();
+                let mut text_cnt = 0;
+                self.history
+                    .iter()
+                    .rposition(|entry| {
+                        text_cnt += entry.text as usize;
+                        text_cnt >= count
+                    })
+                    .unwrap_or(0)
+            }
+            RateWindow::Time => match self.history.back() {
+                Some(latest)
+                    if now.saturating_duration_since(latest.time) <= Self::window_length() =>
+                {
+                    let window = Self::window_length();
+                    self.history
+                        .iter()
+                        .position(|entry| latest.active - entry.active <= window)
+                        .unwrap_or(len)
+                }
+                _ => len,
+            },
+        };
+        self.history.iter().skip(skip)
+    }
+}
+
+impl MistypeDetector for RateDetector {
+    fn name(&self) -> &'static str {
+        "rate"
+    }
+
+    fn feed(&mut self, key: &Key, verdict: Verdict, weight: f64) {
+        //! 履歴にキーを追加する. 範囲より古いキーは自動で削除する.
+        if verdict == Verdict::Ignore {
+            return;
+        }
+
+        let active = self.history.back().map_or(Duration::ZERO, |prev| {
+            prev.active + key.time.saturating_duration_since(prev.time).min(IDLE_GAP)
+        });
+        let text = key.is_text();
+        self.history.push_back(HistoryEntry {
+            time: key.time,
+            active,
+            weight,
+            text,
+        });
+        self.text_cnt += text as usize;
+        self.learn(weight, text, key.time);
+
+        // 設定が変更された場合に備えて、キー数と時間窓の両方の範囲を残す
+        let count = setting::get_count();
+        let window = Self::window_length();
+        while let Some(oldest) = self.history.front() {
+            let is_out_of_count = self.text_cnt - oldest.text as usize >= count;
+            let is_out_of_window = active - oldest.active > window;
+            if self.history.len() <= MAX_HISTORY_SIZE && !(is_out_of_count && is_out_of_window) {
+                break;
+            }
+            self.text_cnt -= oldest.text as usize;
+            self.history.pop_front();
+        }
+    }
+
+    fn retract(&mut self, corrections: usize) {
+        //! 直近のミスタイプ修正の重みを 0 にする. キー入力としては履歴に残す.
+        //! 学習済みの区間の修正は、学習前の区間の重みからは差し引かない.
+        for (age, entry) in self
+            .history
+            .iter_mut()
+            .rev()
+            .enumerate()
+            .filter(|(_, entry)| entry.weight > 0.0)
+            .take(corrections)
+        {
+            if age < self.block_keys {
+                self.block_weight = (self.block_weight - entry.weight).max(0.0);
+            }
+            entry.weight = 0.0;
+        }
+    }
+
+    fn flush(&mut self) {
+        //! 保存していない学習状態をファイルに保存する
+        self.baseline.flush();
+    }
+
+    fn evaluate(&self, now: Instant) -> Evaluation {
+        //! 範囲内の文字入力に対するミスタイプ修正の割合と、閾値を返す.
+        //! 範囲内の文字入力のキー数が `min_samples` に満たない場合は、判定に十分な入力がないものとする.
+        let (weight, samples) = self
+            .recent(setting::get_window(), now)
+            .fold((0.0, 0usize), |(weight, cnt), entry| {
+                (weight + entry.weight, cnt + entry.text as usize)
+            });
+        Evaluation {
+            name: self.name(),
+            score: if samples == 0 {
+                0.0
+            } else {
+                weight / samples as f64
+            },
+            limit: self.limit(),
+            samples,
+            ready: samples >= setting::get_min_samples(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::correction::Correction;
+    use crate::utils::keyhook::keycode::{VK_BACK, VK_LEFT};
+    use crate::utils::keyhook::KeyKind;
+
+    const A: u32 = b'A' as u32;
+
+    /// 一定の時刻から始まるキー入力を、学習状態を保存しない検出器に渡す
+    struct Timeline {
+        detector: RateDetector,
+        started: Instant,
+        elapsed: Duration, // 最後に入力したキーの、開始からの経過時間
+    }
+
+    impl Timeline {
+        fn new() -> Self {
+            Self {
+                detector: RateDetector::with_baseline(Baseline::default()),
+                started: Instant::now(),
+                elapsed: Duration::ZERO,
+            }
+        }
+
+        fn now(&self) -> Instant {
+            //! 最後に入力したキーの時刻を返す
+            self.started + self.elapsed
+        }
+
+        fn press(&mut self, code: u32, weight: f64, gap: Duration) {
+            //! 前のキーから `gap` 後にキーを押下する. 重みがある場合はミスタイプ修正とする
+            self.elapsed += gap;
+            let key = Key::new(code, KeyKind::Press, false).with_time(self.now());
+            let verdict = if weight > 0.0 {
+                Verdict::Mistype(Correction::BackSpace)
+            } else {
+                Verdict::Input
+            };
+            self.detector.feed(&key, verdict, weight);
+        }
+
+        fn type_text(&mut self, len: usize) {
+            //! 100ms 間隔で `len` 文字入力する
+            for _ in 0..len {
+                self.press(A, 0.0, Duration::from_millis(100));
+            }
+        }
+
+        fn correct(&mut self, weight: f64) {
+            //! 100ms 後に重み `weight` のミスタイプ修正をする
+            self.press(VK_BACK, weight, Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn retract_spares_learned_block() {
+        //! 取り消した修正のうち、学習前の区間の修正だけを区間の重みから差し引くこと
+        let mut timeline = Timeline::new();
+        timeline.correct(1.0);
+        timeline.type_text(setting::get_count());
+        assert_eq!(timeline.detector.block_weight, 0.0);
+
+        timeline.correct(2.0);
+        timeline.type_text(1);
+        timeline.correct(1.0);
+        assert_eq!(timeline.detector.block_weight, 3.0);
+        timeline.detector.retract(1);
+        assert_eq!(timeline.detector.block_weight, 2.0);
+
+        // 学習済みの区間の修正まで取り消しても、区間の重みは学習前の区間の分だけ減る
+        timeline.detector.retract(2);
+        assert_eq!(timeline.detector.block_weight, 0.0);
+        assert!(timeline
+            .detector
+            .history
+            .iter()
+            .all(|entry| entry.weight == 0.0));
+    }
+
+    fn measure(timeline: &Timeline, window: RateWindow) -> (f64, usize) {
+        //! 最後の入力の時刻で、範囲 `window` 内のミスタイプ修正の重みの合計と文字入力のキー数を返す
+        timeline
+            .detector
+            .recent(window, timeline.now())
+            .fold((0.0, 0), |(weight, cnt), entry| {
+                (weight + entry.weight, cnt + entry.text as usize)
+            })
+    }
+
+    #[test]
+    fn count_window_covers_recent_text_keys() {
+        //! キー数の範囲では、直近 `count` 回の文字入力以降だけを数えること
+        let count = setting::get_count();
+        let mut timeline = Timeline::new();
+        for _ in 0..10 {
+            timeline.correct(1.0);
+        }
+        timeline.type_text(count);
+        assert_eq!(measure(&timeline, RateWindow::Count), (0.0, count));
+
+        timeline.correct(1.0);
+        timeline.correct(1.0);
+        assert_eq!(measure(&timeline, RateWindow::Count), (2.0, count));
+    }
+
+    #[test]
+    fn time_window_caps_idle_gap() {
+        //! 時間窓の範囲では、入力の途切れを `IDLE_GAP` までの打鍵時間として数えること
+        let window = RateDetector::window_length();
+        let mut timeline = Timeline::new();
+        timeline.correct(1.0);
+        timeline.correct(1.0);
+        // 時間窓より長い休憩の後も、休憩は `IDLE_GAP` だけの打鍵時間となり、休憩前の修正は範囲内に残る
+        timeline.press(A, 0.0, window * 2);
+        timeline.type_text(99);
+        assert_eq!(measure(&timeline, RateWindow::Time), (2.0, 100));
+
+        // 打鍵時間が時間窓を超えると、休憩前の修正は範囲外となる
+        let keys = (window - IDLE_GAP).as_millis() as usize / 100;
+        timeline.type_text(keys);
+        assert_eq!(measure(&timeline, RateWindow::Time).0, 0.0);
+
+        // 最後の入力から時間窓以上経過した場合は、範囲内の入力はない
+        timeline.elapsed += window + Duration::from_secs(1);
+        assert_eq!(measure(&timeline, RateWindow::Time), (0.0, 0));
+    }
+
+    #[test]
+    fn not_ready_below_min_samples() {
+        //! 範囲内の文字入力が `min_samples` に満たない間は、判定に十分な入力がないとすること
+        let min_samples = setting::get_min_samples();
+        let mut timeline = Timeline::new();
+        timeline.type_text(min_samples - 1);
+        timeline.correct(1.0);
+        let evaluation = timeline.detector.evaluate(timeline.now());
+        assert_eq!(evaluation.samples, min_samples - 1);
+        assert!(!evaluation.ready);
+        assert!(!evaluation.is_exceeded(1.0));
+
+        timeline.type_text(1);
+        let evaluation = timeline.detector.evaluate(timeline.now());
+        assert!(evaluation.ready);
+        assert_eq!(evaluation.score, 1.0 / min_samples as f64);
+    }
+
+    #[test]
+    fn denominator_counts_only_text_keys() {
+        //! カーソル移動や修正のキーは、ミスタイプ率の分母に数えないこと
+        let mut timeline = Timeline::new();
+        timeline.type_text(60);
+        for _ in 0..40 {
+            timeline.press(VK_LEFT, 0.0, Duration::from_millis(100));
+        }
+        for _ in 0..3 {
+            timeline.correct(1.0);
+        }
+        let evaluation = timeline.detector.evaluate(timeline.now());
+        assert_eq!(evaluation.samples, 60);
+        assert_eq!(evaluation.score, 3.0 / 60.0);
+    }
+}
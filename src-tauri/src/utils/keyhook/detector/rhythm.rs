@@ -0,0 +1,314 @@
+/**
+ * fatigue detector by typing speed and rhythm
+ */
+use super::{Evaluation, MistypeDetector};
+use crate::utils::keyhook::{activity, correction::Verdict, Key};
+use crate::utils::setting;
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use serde::Serialize;
+
+// これより長い打鍵間隔は考え中などによる中断とし、打鍵間隔の統計に含めない
+const PAUSE: Duration = Duration::from_secs(2);
+
+// これより長くキーボードとマウスの操作が途切れた場合は、休憩を挟んだものとして新しいセッションを開始する
+const SESSION_GAP: Duration = Duration::from_secs(10 * 60);
+
+/// 打鍵間隔の個数・合計・二乗和
+#[derive(Clone, Copy, Default)]
+struct Moments {
+    n: usize,    // 個数
+    sum: f64,    // 合計 [ms]
+    sum_sq: f64, // 二乗和 [ms^2]
+}
+
+impl Moments {
+    fn push(&mut self, value: f64) {
+        //! 値を1件追加する
+        self.n += 1;
+        self.sum += value;
+        self.sum_sq += value * value;
+    }
+
+    fn mean(&self) -> f64 {
+        //! 平均を返す. 値がない場合は 0 とする.
+        if self.n == 0 {
+            0.0
+        } else {
+            self.sum / self.n as f64
+        }
+    }
+
+    fn stddev(&self) -> f64 {
+        //! 標準偏差を返す. 値がない場合は 0 とする.
+        if self.n == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_sq / self.n as f64 - mean * mean).max(0.0).sqrt()
+    }
+}
+
+/// セッション開始時の打鍵の指標 (疲れていない状態の基準)
+#[derive(Clone, Copy, Debug)]
+struct StartStats {
+    kpm: f64,             // 1分あたりの文字入力のキー数
+    interval_mean: f64,   // 打鍵間隔の平均 [ms]
+    interval_stddev: f64, // 打鍵間隔の標準偏差 [ms]
+}
+
+/// 休憩を挟まずに連続して入力している期間
+struct Session {
+    started: Instant,          // 開始時刻
+    keys: usize,               // 基準を求めるまでの文字入力のキー数
+    intervals: Moments,        // 基準を求めるまでの打鍵間隔
+    start: Option<StartStats>, // セッション開始時の基準. `rhythm_samples` 個の打鍵間隔が集まるまでは `None`
+}
+
+impl Session {
+    fn new(started: Instant) -> Self {
+        //! コンストラクタ
+        Self {
+            started,
+            keys: 0,
+            intervals: Moments::default(),
+            start: None,
+        }
+    }
+}
+
+/// 打鍵速度と打鍵間隔の指標
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TypingMetrics {
+    pub kpm: f64,                  // 1分あたりの文字入力のキー数
+    pub interval_mean: f64,        // 打鍵間隔の平均 [ms]
+    pub interval_stddev: f64,      // 打鍵間隔の標準偏差 [ms]
+    pub samples: usize,            // 計算に使用した打鍵間隔の数
+    pub kpm_drift: Option<f64>,    // セッション開始時からの `kpm` の変化率 (遅くなると負)
+    pub mean_drift: Option<f64>,   // セッション開始時からの `interval_mean` の変化率 (遅くなると正)
+    pub stddev_drift: Option<f64>, // セッション開始時からの `interval_stddev` の変化率 (乱れると正)
+}
+
+/// 打鍵が遅くなる、リズムが乱れるといった疲れの兆候で判定する検出器.
+/// 直近 `rhythm_window` の文字入力の打鍵間隔を、セッション開始時の打鍵間隔と比較し、
+/// 平均または標準偏差の増加率が `rhythm_drift` を超えると判定する.
+#[derive(Default)]
+pub struct RhythmDetector {
+    history: VecDeque<(Instant, Option<f64>)>, // 時間窓内の文字入力の時刻と、直前の文字入力からの間隔 [ms]
+    session: Option<Session>,                  // 現在のセッション
+}
+
+impl RhythmDetector {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn metrics(&self, now: Instant) -> TypingMetrics {
+        //! 時刻 `now` から遡って `rhythm_window` の打鍵速度と打鍵間隔の指標を返す
+        let Some(session) = &self.session else {
+            return TypingMetrics::default();
+        };
+        let window = setting::get_detector().rhythm_window.as_duration();
+
+        let mut keys = 0;
+        let mut intervals = Moments::default();
+        for (time, interval) in self.history.iter() {
+            if now.saturating_duration_since(*time) > window {
+                continue;
+            }
+            keys += 1;
+            if let Some(interval) = interval {
+                intervals.push(*interval);
+            }
+        }
+
+        // セッションが時間窓より短い場合は、セッションの長さで割る
+        let span = window.min(now.saturating_duration_since(session.started));
+        let kpm = if span.is_zero() {
+            0.0
+        } else {
+            keys as f64 * 60.0 / span.as_secs_f64()
+        };
+        let interval_mean = intervals.mean();
+        let interval_stddev = intervals.stddev();
+
+        let drift = |current: f64, start: f64| (start > 0.0).then(|| current / start - 1.0);
+        TypingMetrics {
+            kpm,
+            interval_mean,
+            interval_stddev,
+            samples: intervals.n,
+            kpm_drift: session.start.and_then(|start| drift(kpm, start.kpm)),
+            mean_drift: session
+                .start
+                .and_then(|start| drift(interval_mean, start.interval_mean)),
+            stddev_drift: session
+                .start
+                .and_then(|start| drift(interval_stddev, start.interval_stddev)),
+        }
+    }
+}
+
+impl MistypeDetector for RhythmDetector {
+    fn name(&self) -> &'static str {
+        "rhythm"
+    }
+
+    fn feed(&mut self, key: &Key, verdict: Verdict, _weight: f64) {
+        //! 文字入力のキーの打鍵間隔を記録する. 時間窓より古い記録は自動で削除する.
+        //! キーボードの故障による押下など、判定の対象外の押下は記録しない.
+        if !key.is_text() || verdict == Verdict::Ignore {
+            return;
+        }
+
+        let last = self.history.back().map(|(time, _)| *time);
+        let gap = last.map(|last| key.time.saturating_duration_since(last));
+        // キー入力が途切れていても、マウスを操作していた場合は休憩とみなさない
+        let idle = last.map(|last| activity::longest_idle(last, key.time));
+        if self.session.is_none() || idle.is_some_and(|idle| idle > SESSION_GAP) {
+            debug!("Start new typing session");
+            self.session = Some(Session::new(key.time));
+            self.history.clear();
+        }
+        let interval = gap
+            .filter(|gap| *gap <= PAUSE && !self.history.is_empty())
+            .map(|gap| gap.as_secs_f64() * 1000.0);
+        self.history.push_back((key.time, interval));
+
+        let config = setting::get_detector();
+        let window = config.rhythm_window.as_duration();
+        while let Some((oldest, _)) = self.history.front() {
+            if key.time.saturating_duration_since(*oldest) <= window {
+                break;
+            }
+            self.history.pop_front();
+        }
+
+        // セッション開始から `rhythm_samples` 個の打鍵間隔で基準を求める
+        let session = self.session.as_mut().expect("session started above");
+        if session.start.is_some() {
+            return;
+        }
+        session.keys += 1;
+        if let Some(interval) = interval {
+            session.intervals.push(interval);
+        }
+        if session.intervals.n >= config.rhythm_samples.max(1) {
+            let elapsed = key.time.saturating_duration_since(session.started);
+            let start = StartStats {
+                kpm: session.keys as f64 * 60.0 / elapsed.as_secs_f64().max(1.0),
+                interval_mean: session.intervals.mean(),
+                interval_stddev: session.intervals.stddev(),
+            };
+            debug!("Typing session baseline: {:?}", start);
+            session.start = Some(start);
+        }
+    }
+
+    fn evaluate(&self, now: Instant) -> Evaluation {
+        //! 打鍵間隔の平均と標準偏差のうち、セッション開始時からの増加率が大きい方と、閾値を返す.
+        //! セッション開始時の基準がない場合や、打鍵間隔の数が `min_samples` に満たない場合は、
+        //! 判定に十分な入力がないものとする.
+        let metrics = self.metrics(now);
+        let score = metrics
+            .mean_drift
+            .unwrap_or(0.0)
+            .max(metrics.stddev_drift.unwrap_or(0.0));
+        Evaluation {
+            name: self.name(),
+            score,
+            limit: setting::get_detector().rhythm_drift,
+            samples: metrics.samples,
+            ready: metrics.mean_drift.is_some() && metrics.samples >= setting::get_min_samples(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::KeyKind;
+
+    /// 一定の時刻から始まる文字入力を検出器に渡す
+    struct Timeline {
+        detector: RhythmDetector,
+        now: Instant, // 最後に入力したキーの時刻
+    }
+
+    impl Timeline {
+        fn new() -> Self {
+            Self {
+                detector: RhythmDetector::new(),
+                now: Instant::now(),
+            }
+        }
+
+        fn type_for(&mut self, duration: Duration, mean: u64, spread: u64) {
+            //! `duration` の間、平均 `mean` ms, 標準偏差 `spread` ms の打鍵間隔で交互に入力する
+            let end = self.now + duration;
+            let mut slow = false;
+            while self.now < end {
+                slow = !slow;
+                let interval = if slow { mean + spread } else { mean - spread };
+                self.now += Duration::from_millis(interval);
+                let key = Key::new(b'A' as u32, KeyKind::Press, false).with_time(self.now);
+                self.detector.feed(&key, Verdict::Input, 0.0);
+            }
+        }
+
+        fn start_session(&mut self) {
+            //! セッション開始時の基準が決まるまで、平均 200ms, 標準偏差 20ms の打鍵間隔で入力する
+            let samples = setting::get_detector().rhythm_samples;
+            let key = Key::new(b'A' as u32, KeyKind::Press, false).with_time(self.now);
+            self.detector.feed(&key, Verdict::Input, 0.0);
+            self.type_for(Duration::from_millis(200) * samples as u32, 200, 20);
+            let session = self.detector.session.as_ref().unwrap();
+            assert!(session.start.is_some());
+        }
+    }
+
+    #[test]
+    fn steady_cadence_is_not_exceeded() {
+        //! セッション開始時と同じリズムで入力を続ける間は、増加率がほぼ 0 で閾値を超えないこと
+        let mut timeline = Timeline::new();
+        timeline.start_session();
+        timeline.type_for(Duration::from_secs(180), 200, 20);
+
+        let metrics = timeline.detector.metrics(timeline.now);
+        assert!(metrics.mean_drift.unwrap().abs() < 0.01, "{:?}", metrics);
+        assert!(metrics.stddev_drift.unwrap().abs() < 0.01, "{:?}", metrics);
+        let evaluation = timeline.detector.evaluate(timeline.now);
+        assert!(evaluation.ready);
+        assert!(!evaluation.is_exceeded(1.0), "{:?}", evaluation);
+    }
+
+    #[test]
+    fn growing_variance_is_exceeded() {
+        //! 打鍵速度が変わらなくても、打鍵間隔のばらつきが増えていくと増加率が上がり、閾値を超えること
+        let mut timeline = Timeline::new();
+        timeline.start_session();
+
+        let mut scores = Vec::new();
+        for spread in [20, 40, 80, 160] {
+            timeline.type_for(Duration::from_secs(60), 200, spread);
+            scores.push(timeline.detector.evaluate(timeline.now).score);
+        }
+        assert!(
+            scores.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            scores
+        );
+
+        let metrics = timeline.detector.metrics(timeline.now);
+        assert!(metrics.mean_drift.unwrap().abs() < 0.01, "{:?}", metrics);
+        let evaluation = timeline.detector.evaluate(timeline.now);
+        assert!(evaluation.ready);
+        assert!(evaluation.is_exceeded(1.0), "{:?}", evaluation);
+    }
+}
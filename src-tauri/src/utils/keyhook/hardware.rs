@@ -0,0 +1,294 @@
+/**
+ * keyboard hardware diagnostics: switch chatter and repeats without holding the key
+ */
+use super::correction::Verdict;
+use super::keycode::KeyClass;
+use super::{Key, KeyKind};
+use crate::utils::setting;
+
+use std::{collections::BTreeMap, time::Instant};
+
+use log::{debug, warn};
+use serde::Serialize;
+
+// 故障による余分な入力を、この文字数の入力の間に修正しなかった場合は、修正しないものとみなす
+const FORGIVE_WINDOW: usize = 10;
+
+/// 故障が疑われる入力の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    Chatter,       // 1回の打鍵で、数ミリ秒以内に押下が2回登録された (スイッチのチャタリング)
+    PhantomRepeat, // 押し続けていないのに、同じキーの押下が短い間隔で繰り返された
+}
+
+/// キーごとの、故障が疑われる入力の記録
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct KeyFault {
+    pub code: u32,             // キーコード
+    pub chatter: usize,        // チャタリングの回数
+    pub phantom_repeat: usize, // 押していない連続入力の回数
+    pub last_gap_ms: u64,      // 最後に検出した入力の、直前の押下からの間隔
+}
+
+impl KeyFault {
+    pub fn total(&self) -> usize {
+        //! 故障が疑われる入力の回数を返す
+        self.chatter + self.phantom_repeat
+    }
+
+    pub fn label(&self) -> String {
+        //! 通知に表示するキーの名前を返す. 英数字以外は仮想キーコードで表す.
+        match char::from_u32(self.code) {
+            Some(c) if c.is_ascii_uppercase() || c.is_ascii_digit() => c.to_string(),
+            _ => format!("0x{:02X}", self.code),
+        }
+    }
+}
+
+/// 直前の押下
+#[derive(Clone, Copy)]
+struct LastPress {
+    code: u32,      // キーコード
+    time: Instant,  // 押下時刻
+    released: bool, // 押下の後に解放されたかどうか
+    run: usize,     // 短い間隔で続いた同じキーの押下の回数
+}
+
+/// 入力時刻から、キーボードの故障が疑われる入力を検出する構造体.
+/// 人の打鍵では起こりえない短い間隔で同じキーの押下と解放が続いた場合は故障とみなし、
+/// 余分な押下自体と、余分に入力された文字の削除をミスタイプ修正として数えないようにする.
+#[derive(Default)]
+pub struct HardwareTracker {
+    last: Option<LastPress>,         // 直前の押下 (修飾キーを除く)
+    last_spurious: bool,             // 直前に判定した押下が、故障による余分な入力かどうか
+    spurious: usize,                 // 故障により余分に入力され、まだ修正されていない入力の数
+    typed: usize,                    // 最後に故障を検出してから入力した文字数
+    faults: BTreeMap<u32, KeyFault>, // キーごとの、故障が疑われる入力の記録
+}
+
+impl HardwareTracker {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn faults(&self) -> Vec<KeyFault> {
+        //! 故障が疑われる入力があったキーの記録を、キーコード順に返す
+        self.faults.values().copied().collect()
+    }
+
+    pub fn is_spurious(&self) -> bool {
+        //! 直前に `track` で判定した押下が、故障による余分な入力かどうかを返す
+        self.last_spurious
+    }
+
+    pub fn track(&mut self, key: &Key) -> Option<KeyFault> {
+        //! キーの押下・解放を受け取り、故障が疑われる入力かどうかを判定する.
+        //! 判定した押下が余分な入力かどうかは `is_spurious` で取得する.
+        //! 1つのキーで故障が疑われる入力が `notify_after` 回に達した場合は、そのキーの記録を返す.
+        self.last_spurious = false;
+        if key.class == KeyClass::Modifier {
+            return None;
+        }
+        match key.kind {
+            KeyKind::Release => {
+                if let Some(last) = self.last.as_mut().filter(|last| last.code == key.code) {
+                    last.released = true;
+                }
+                return None;
+            }
+            // 押し続けたことによるオートリピートは故障ではない
+            KeyKind::Press if key.repeat => return None,
+            KeyKind::Press => (),
+        }
+
+        let config = setting::get_hardware();
+        let prev = self
+            .last
+            .filter(|prev| prev.code == key.code && prev.released);
+        let mut last = LastPress {
+            code: key.code,
+            time: key.time,
+            released: false,
+            run: 1,
+        };
+        let detected = prev.and_then(|prev| {
+            let gap = key.time.saturating_duration_since(prev.time);
+            if gap < config.chatter_window.as_duration() {
+                // チャタリングの押下は連続入力の回数に含めない
+                last.run = prev.run;
+                Some((FaultKind::Chatter, gap, 1))
+            } else if gap < config.repeat_interval.as_duration() {
+                last.run = prev.run + 1;
+                // 最初の押下は意図した入力とし、それ以降を余分な入力とする
+                (last.run == config.repeat_count.max(2)).then_some((
+                    FaultKind::PhantomRepeat,
+                    gap,
+                    last.run - 1,
+                ))
+            } else {
+                None
+            }
+        });
+        self.last = Some(last);
+
+        let Some((kind, gap, spurious)) = detected else {
+            // 検出済みの連続入力が続いている場合は、余分な入力を加える
+            if last.run > config.repeat_count.max(2) {
+                self.last_spurious = true;
+                self.add_spurious(1);
+            }
+            return None;
+        };
+        // 連続入力を検出するまでの押下は判定済みのため、検出した押下から余分な入力とする
+        self.last_spurious = true;
+
+        debug!(
+            "Detect {:?} on key {}: {} ms after the previous press",
+            kind,
+            key.code,
+            gap.as_millis()
+        );
+        self.add_spurious(spurious);
+        let fault = self.faults.entry(key.code).or_insert(KeyFault {
+            code: key.code,
+            ..Default::default()
+        });
+        match kind {
+            FaultKind::Chatter => fault.chatter += 1,
+            FaultKind::PhantomRepeat => fault.phantom_repeat += 1,
+        }
+        fault.last_gap_ms = gap.as_millis() as u64;
+
+        if fault.total() == config.notify_after {
+            warn!("Key {} may be faulty: {:?}", fault.label(), fault);
+            Some(*fault)
+        } else {
+            None
+        }
+    }
+
+    fn add_spurious(&mut self, count: usize) {
+        //! 故障により余分に入力された入力を記録する
+        self.spurious += count;
+        self.typed = 0;
+    }
+
+    pub fn absorb(&mut self, key: &Key, verdict: Verdict) -> bool {
+        //! キー押下と判定結果を受け取り、故障による余分な入力の修正かどうかを返す.
+        //! 余分な入力の数だけ、その後のミスタイプ修正を故障の修正とみなす.
+        if self.spurious == 0 {
+            return false;
+        }
+        match verdict {
+            Verdict::Mistype(_) => {
+                self.spurious -= 1;
+                debug!("Absorb correction of a faulty key press");
+                true
+            }
+            Verdict::Input if key.is_text() => {
+                self.typed += 1;
+                if self.typed > FORGIVE_WINDOW {
+                    self.spurious = 0;
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::correction::Correction;
+    use crate::utils::keyhook::keycode::VK_BACK;
+    use std::time::Duration;
+
+    const A: u32 = b'A' as u32;
+    const B: u32 = b'B' as u32;
+
+    /// 開始時刻からの経過時間でキー入力を渡すヘルパー
+    struct Timeline {
+        tracker: HardwareTracker,
+        started: Instant,
+    }
+
+    impl Timeline {
+        fn new() -> Self {
+            //! コンストラクタ
+            Self {
+                tracker: HardwareTracker::new(),
+                started: Instant::now(),
+            }
+        }
+
+        fn key(&self, code: u32, kind: KeyKind, ms: u64) -> Key {
+            //! 開始から `ms` ミリ秒後のキー入力を返す
+            Key::new(code, kind, false).with_time(self.started + Duration::from_millis(ms))
+        }
+
+        fn tap(&mut self, code: u32, ms: u64) -> (bool, Option<KeyFault>) {
+            //! `ms` ミリ秒後に押下し、5ミリ秒後に解放する. 押下が余分な入力かどうかと、通知する記録を返す
+            let fault = self.tracker.track(&self.key(code, KeyKind::Press, ms));
+            let spurious = self.tracker.is_spurious();
+            self.tracker
+                .track(&self.key(code, KeyKind::Release, ms + 5));
+            (spurious, fault)
+        }
+
+        fn absorb_backspace(&mut self) -> bool {
+            //! BackSpace によるミスタイプ修正が、故障による余分な入力の修正かどうかを返す
+            let key = self.key(VK_BACK, KeyKind::Press, 0);
+            self.tracker
+                .absorb(&key, Verdict::Mistype(Correction::BackSpace))
+        }
+    }
+
+    #[test]
+    fn normal_typing_is_not_spurious() {
+        //! 人の打鍵の間隔で同じキーを押した場合は、故障とみなさない
+        let mut timeline = Timeline::new();
+        assert_eq!(timeline.tap(A, 0), (false, None));
+        assert_eq!(timeline.tap(A, 150), (false, None));
+        assert!(timeline.tracker.faults().is_empty());
+        assert!(!timeline.absorb_backspace());
+    }
+
+    #[test]
+    fn chatter_press_is_spurious() {
+        //! チャタリングの押下は余分な入力とし、その削除を1回だけ故障の修正とみなす
+        let mut timeline = Timeline::new();
+        assert_eq!(timeline.tap(A, 0), (false, None));
+        let (spurious, _) = timeline.tap(A, 10);
+        assert!(spurious);
+        assert_eq!(timeline.tracker.faults()[0].chatter, 1);
+        assert!(timeline.absorb_backspace());
+        assert!(!timeline.absorb_backspace());
+    }
+
+    #[test]
+    fn phantom_repeat_is_spurious_from_detection() {
+        //! 押していない連続入力は、検出した押下から余分な入力とし、最初の押下以外の削除を故障の修正とみなす
+        let mut timeline = Timeline::new();
+        let spurious: Vec<bool> = (0..5).map(|i| timeline.tap(B, i * 40).0).collect();
+        assert_eq!(spurious, vec![false, false, false, true, true]);
+        assert_eq!(timeline.tracker.faults()[0].phantom_repeat, 1);
+        for _ in 0..4 {
+            assert!(timeline.absorb_backspace());
+        }
+        assert!(!timeline.absorb_backspace());
+    }
+
+    #[test]
+    fn fault_is_notified_after_repeated_detection() {
+        //! 1つのキーで故障が疑われる入力が `notify_after` 回に達した場合に通知する
+        let mut timeline = Timeline::new();
+        timeline.tap(A, 0);
+        assert!(timeline.tap(A, 10).1.is_none());
+        assert!(timeline.tap(A, 20).1.is_none());
+        let fault = timeline.tap(A, 30).1.expect("notified");
+        assert_eq!((fault.chatter, fault.label()), (3, "A".to_string()));
+    }
+}
@@ -0,0 +1,314 @@
+/**
+ * detection of phrases typed in the wrong IME input mode
+ */
+use super::correction::Verdict;
+use super::keycode::{VK_ESCAPE, VK_HANJA, VK_KANA, VK_OEM_3, VK_OEM_AUTO, VK_OEM_COPY};
+use super::Key;
+use crate::utils::setting;
+
+use log::{debug, info};
+use serde::Serialize;
+
+fn is_mode_toggle(key: &Key) -> bool {
+    //! IME の入力モードを切り替えるキーかどうかを返す.
+    //! 半角/全角, 漢字, かな, カタカナ/ひらがな, Alt+`
+    match key.code {
+        VK_OEM_AUTO | VK_HANJA | VK_KANA | VK_OEM_COPY => true,
+        VK_OEM_3 => key.modifiers.alt,
+        _ => false,
+    }
+}
+
+/// 入力モードの間違いとみなした入力
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ModeMishap {
+    pub typed: usize,   // 間違ったモードで入力した文字数
+    pub deleted: usize, // 削除した文字数 (Esc で変換を取り消した場合は入力した文字数)
+}
+
+/// 入力モードの間違いの統計
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ModeStats {
+    pub count: usize,             // 監視開始からの入力モードの間違いの回数
+    pub last: Option<ModeMishap>, // 最後の入力モードの間違い
+}
+
+/// 判定の結果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeVerdict {
+    None, // 入力モードの間違いではない
+    // 入力モードの間違い. 直前の `corrections` 回のミスタイプ修正は、ミスタイプとして数えない
+    Mishap {
+        mishap: ModeMishap,
+        corrections: usize,
+    },
+}
+
+/// 「ひと続きの文字を入力 → まとめて削除 → 入力モードを切り替えて入力し直す」という並びを検出する構造体.
+/// 入力モードの切り替えは、切り替えキーの押下か、入力し直した文字で IME の未確定文字列ができたかどうかが
+/// 削除前の入力と異なることで判定する.
+/// `ime_composition` は未確定文字列があるかどうかのため、ひらがななどの入力モードでも最初のキーは `false` となる.
+/// そのため、ひと続きの入力のいずれかのキーで `true` であれば IME で入力したものとし、
+/// 入力し直した文字は2文字目まで見てから判定する.
+/// 変換の確定と取り消しは区別しない. Esc での取り消しは Esc キーの押下で判定するため、
+/// Windows で取り消しが確定として通知される (`composition_win32`) ことの影響は受けない.
+#[derive(Default)]
+pub struct ModeTracker {
+    typed: usize,          // 削除前にひと続きで入力した文字数
+    composed: bool,        // 削除前の入力で IME の未確定文字列ができたかどうか
+    deleted: usize,        // 削除した文字数
+    corrections: usize,    // 削除の間のミスタイプ修正の回数
+    toggled: bool,         // 入力の後に入力モードを切り替えたかどうか
+    retyped: Option<bool>, // 削除の後に入力し直した最初の文字で、未確定文字列があったかどうか
+    stats: ModeStats,      // 統計
+}
+
+impl ModeTracker {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn stats(&self) -> ModeStats {
+        //! 入力モードの間違いの統計を返す
+        self.stats
+    }
+
+    fn reset(&mut self) {
+        //! ひと続きの入力の記録を破棄する
+        self.typed = 0;
+        self.composed = false;
+        self.deleted = 0;
+        self.corrections = 0;
+        self.toggled = false;
+        self.retyped = None;
+    }
+
+    fn restart(&mut self, typed: usize, composed: bool) {
+        //! 入力し直した文字を、新しいひと続きの入力として記録する
+        self.reset();
+        self.typed = typed;
+        self.composed = composed;
+    }
+
+    pub fn feed(&mut self, key: &Key, verdict: Verdict) -> ModeVerdict {
+        //! キー押下と判定結果を受け取り、入力モードの間違いかどうかを返す
+        if is_mode_toggle(key) {
+            self.toggled = true;
+            return ModeVerdict::None;
+        }
+
+        match verdict {
+            Verdict::Mistype(_) if self.typed > 0 && self.retyped.is_none() => {
+                self.deleted += 1;
+                self.corrections += 1;
+                ModeVerdict::None
+            }
+            // 変換中の文字列を Esc でまとめて取り消した場合も、入力した文字数分の削除とする
+            Verdict::Input if key.code == VK_ESCAPE && self.composed && self.deleted == 0 => {
+                self.deleted = self.typed;
+                ModeVerdict::None
+            }
+            Verdict::Input if key.is_text() => {
+                if self.deleted == 0 {
+                    if self.typed == 0 || self.toggled {
+                        self.restart(0, false);
+                    }
+                    self.typed += 1;
+                    self.composed |= key.ime_composition;
+                    return ModeVerdict::None;
+                }
+
+                match self.retyped {
+                    // 切り替えキーを押した場合は、最初の文字で判定する
+                    None if self.toggled => {
+                        let verdict = self.judge(true);
+                        self.restart(1, key.ime_composition);
+                        verdict
+                    }
+                    // IME の入力モードでは2文字目の入力時に未確定文字列があるため、次の文字まで判定を待つ
+                    None => {
+                        self.retyped = Some(key.ime_composition);
+                        ModeVerdict::None
+                    }
+                    Some(first) => {
+                        let composed = first || key.ime_composition;
+                        let verdict = self.judge(composed != self.composed);
+                        self.restart(2, composed);
+                        verdict
+                    }
+                }
+            }
+            Verdict::Ignore => ModeVerdict::None,
+            _ => {
+                self.reset();
+                ModeVerdict::None
+            }
+        }
+    }
+
+    fn judge(&mut self, switched: bool) -> ModeVerdict {
+        //! 削除の後に入力し直した文字で、入力モードを切り替えて入力し直したかどうかを判定する
+        let config = setting::get_ime_mode();
+        if !switched || self.typed < config.min_typed || self.deleted < config.min_deleted {
+            debug!(
+                "Not a mode mishap: typed {}, deleted {}, switched {}",
+                self.typed, self.deleted, switched
+            );
+            return ModeVerdict::None;
+        }
+
+        let mishap = ModeMishap {
+            typed: self.typed,
+            deleted: self.deleted,
+        };
+        info!("Detect IME mode mishap: {:?}", mishap);
+        self.stats.count += 1;
+        self.stats.last = Some(mishap);
+        ModeVerdict::Mishap {
+            mishap,
+            corrections: self.corrections,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::correction::Correction;
+    use crate::utils::keyhook::keycode::VK_BACK;
+    use crate::utils::keyhook::KeyKind;
+
+    const A: u32 = b'A' as u32;
+
+    fn press(tracker: &mut ModeTracker, code: u32, ime_composition: bool) -> ModeVerdict {
+        //! キー押下を渡す. BackSpace はミスタイプ修正、それ以外は入力とする
+        let key = Key::new(code, KeyKind::Press, ime_composition);
+        let verdict = match code {
+            VK_BACK => Verdict::Mistype(Correction::BackSpace),
+            _ => Verdict::Input,
+        };
+        tracker.feed(&key, verdict)
+    }
+
+    fn type_phrase(tracker: &mut ModeTracker, len: usize, ime: bool) -> Vec<ModeVerdict> {
+        //! `len` 文字入力する. IME の入力モードでは、2文字目以降で未確定文字列がある
+        (0..len).map(|i| press(tracker, A, ime && i > 0)).collect()
+    }
+
+    fn delete(tracker: &mut ModeTracker, len: usize, composing: bool) {
+        //! BackSpace で `len` 文字削除する
+        for _ in 0..len {
+            assert_eq!(press(tracker, VK_BACK, composing), ModeVerdict::None);
+        }
+    }
+
+    fn mishaps(verdicts: &[ModeVerdict]) -> Vec<ModeMishap> {
+        //! 判定結果から、入力モードの間違いを取り出す
+        verdicts
+            .iter()
+            .filter_map(|verdict| match verdict {
+                ModeVerdict::Mishap { mishap, .. } => Some(*mishap),
+                ModeVerdict::None => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn switch_without_toggle_key_is_detected_on_second_char() {
+        //! 切り替えキーを押さずに入力モードを切り替えた場合も、入力し直した2文字目で判定する
+        let expected = ModeMishap {
+            typed: 5,
+            deleted: 5,
+        };
+        for ime in [true, false] {
+            let mut tracker = ModeTracker::new();
+            type_phrase(&mut tracker, 5, ime);
+            delete(&mut tracker, 5, ime);
+            let verdicts = type_phrase(&mut tracker, 5, !ime);
+            assert_eq!(verdicts[0], ModeVerdict::None, "ime={}", ime);
+            assert_eq!(
+                verdicts[1],
+                ModeVerdict::Mishap {
+                    mishap: expected,
+                    corrections: 5
+                },
+                "ime={}",
+                ime
+            );
+            assert_eq!(tracker.stats().count, 1);
+        }
+    }
+
+    #[test]
+    fn retype_in_same_mode_is_not_mishap() {
+        //! 同じ入力モードで入力し直した場合は、入力モードの間違いではない
+        for ime in [true, false] {
+            let mut tracker = ModeTracker::new();
+            type_phrase(&mut tracker, 5, ime);
+            delete(&mut tracker, 5, ime);
+            let verdicts = type_phrase(&mut tracker, 5, ime);
+            assert!(mishaps(&verdicts).is_empty(), "ime={}", ime);
+        }
+    }
+
+    #[test]
+    fn toggle_key_is_judged_on_first_char() {
+        //! 切り替えキーを押した場合は、入力し直した最初の文字で判定する
+        let mut tracker = ModeTracker::new();
+        type_phrase(&mut tracker, 5, false);
+        delete(&mut tracker, 4, false);
+        assert_eq!(press(&mut tracker, VK_OEM_AUTO, false), ModeVerdict::None);
+        assert_eq!(
+            press(&mut tracker, A, false),
+            ModeVerdict::Mishap {
+                mishap: ModeMishap {
+                    typed: 5,
+                    deleted: 4
+                },
+                corrections: 4
+            }
+        );
+    }
+
+    #[test]
+    fn escape_cancels_composed_phrase() {
+        //! IME の未確定文字列を Esc で取り消した場合は、入力した文字数分の削除とする
+        let mut tracker = ModeTracker::new();
+        type_phrase(&mut tracker, 5, true);
+        assert_eq!(press(&mut tracker, VK_ESCAPE, true), ModeVerdict::None);
+        let verdicts = type_phrase(&mut tracker, 5, false);
+        assert_eq!(
+            verdicts[1],
+            ModeVerdict::Mishap {
+                mishap: ModeMishap {
+                    typed: 5,
+                    deleted: 5
+                },
+                corrections: 0
+            }
+        );
+    }
+
+    #[test]
+    fn short_phrase_is_not_mishap() {
+        //! 入力した文字数が `min_typed` に満たない場合は、入力モードの間違いではない
+        let mut tracker = ModeTracker::new();
+        type_phrase(&mut tracker, 3, false);
+        delete(&mut tracker, 3, false);
+        let verdicts = type_phrase(&mut tracker, 3, true);
+        assert!(mishaps(&verdicts).is_empty());
+    }
+
+    #[test]
+    fn other_key_between_retyped_chars_resets() {
+        //! 入力し直した最初の文字の後に文字以外のキーを押した場合は、判定しない
+        let mut tracker = ModeTracker::new();
+        type_phrase(&mut tracker, 5, true);
+        delete(&mut tracker, 5, true);
+        assert_eq!(press(&mut tracker, A, false), ModeVerdict::None);
+        assert_eq!(press(&mut tracker, VK_ESCAPE, false), ModeVerdict::None);
+        let verdicts = type_phrase(&mut tracker, 5, false);
+        assert!(mishaps(&verdicts).is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+/**
+ * keyboard input for Linux (evdev)
+ */
+use super::{composition, keycode, InputSource, Key, KeyKind};
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    os::raw::c_long,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::bail;
+use evdev::{Device, EventType, InputEvent, KeyCode};
+use log::{debug, error, info, warn};
+
+// evdev の EV_KEY イベントの value
+const KEY_VALUE_RELEASE: i32 = 0;
+const KEY_VALUE_PRESS: i32 = 1;
+const KEY_VALUE_REPEAT: i32 = 2;
+
+// 記録済みダンプファイル中の `struct input_event` のサイズ (timeval + type + code + value)
+const TIMEVAL_SIZE: usize = 2 * std::mem::size_of::<c_long>();
+const INPUT_EVENT_SIZE: usize = TIMEVAL_SIZE + 2 + 2 + 4;
+
+fn event_to_key(event: &InputEvent, time: Instant) -> Option<Key> {
+    //! evdev のイベントを `Key` に変換する.
+    //! Windows の `WM_KEYDOWN` と同じく、オートリピートはキー押下として扱い、リピートであることを設定する.
+    if event.event_type() != EventType::KEY {
+        return None;
+    }
+
+    let (kind, repeat) = match event.value() {
+        KEY_VALUE_PRESS => (KeyKind::Press, false),
+        KEY_VALUE_REPEAT => (KeyKind::Press, true),
+        KEY_VALUE_RELEASE => (KeyKind::Release, false),
+        _ => return None,
+    };
+
+    // evdev からは IME の状態を取得できないため、D-Bus で監視している変換状態を使用する
+    keycode::from_evdev(event.code()).map(|code| {
+        Key::new(code, kind, composition::is_composing())
+            .with_time(time)
+            .with_repeat(repeat)
+    })
+}
+
+fn send_key(key: Key, tx: &mpsc::Sender<Key>) -> bool {
+    //! `Key` を送信する.
+    //! * return `false` - 受信側が終了している
+    match tx.send(key) {
+        Ok(()) => {
+            debug!("Send key: {}", key.code);
+            true
+        }
+        Err(mpsc::SendError(e)) => {
+            error!("Failed to send key: {}", e.code);
+            false
+        }
+    }
+}
+
+fn is_keyboard(device: &Device) -> bool {
+    //! 文字入力に使用するキーボードかどうかを返す.
+    //! 電源ボタンやマウスのボタンなども EV_KEY を持つため、主要なキーの有無で判定する.
+    device.supported_keys().is_some_and(|keys| {
+        keys.contains(KeyCode::KEY_A)
+            && keys.contains(KeyCode::KEY_ENTER)
+            && keys.contains(KeyCode::KEY_BACKSPACE)
+    })
+}
+
+pub(super) fn event_time(event: &InputEvent, now: Instant, wall: SystemTime) -> Instant {
+    //! カーネルがイベントに付けた時刻を、読み込んだ時刻 (`now`, `wall`) からの差で `Instant` に変換する.
+    //! まとめて読み込んだイベントも、チャタリングの判定などのため個別の時刻とする.
+    //! 時刻が読み込んだ時刻より後になっている (時計が変更された) 場合は、読み込んだ時刻とする.
+    wall.duration_since(event.timestamp())
+        .ok()
+        .and_then(|age| now.checked_sub(age))
+        .unwrap_or(now)
+}
+
+fn read_device(mut device: Device, tx: mpsc::Sender<Key>) -> io::Result<()> {
+    //! デバイスからイベントを読み続け、`Key` を送信する
+    loop {
+        let events = device.fetch_events()?;
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        for key in events.filter_map(|event| event_to_key(&event, event_time(&event, now, wall))) {
+            if !send_key(key, &tx) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn parse_dump_event(buf: &[u8; INPUT_EVENT_SIZE]) -> (Duration, InputEvent) {
+    //! ダンプファイル中の `struct input_event` 1件分をパースし、記録時刻と共に返す
+    const LONG_SIZE: usize = TIMEVAL_SIZE / 2;
+    let tv_sec = c_long::from_ne_bytes(buf[..LONG_SIZE].try_into().unwrap());
+    let tv_usec = c_long::from_ne_bytes(buf[LONG_SIZE..TIMEVAL_SIZE].try_into().unwrap());
+    let field = &buf[TIMEVAL_SIZE..];
+    let type_ = u16::from_ne_bytes([field[0], field[1]]);
+    let code = u16::from_ne_bytes([field[2], field[3]]);
+    let value = i32::from_ne_bytes([field[4], field[5], field[6], field[7]]);
+
+    let timestamp =
+        Duration::from_secs(tv_sec.max(0) as u64) + Duration::from_micros(tv_usec.max(0) as u64);
+    (timestamp, InputEvent::new(type_, code, value))
+}
+
+fn read_dump(path: &Path, tx: mpsc::Sender<Key>) -> io::Result<()> {
+    //! `cat /dev/input/eventX > dump` などで記録したダンプファイルを読み込み、`Key` を送信する.
+    //! 入力時刻は、読み込み開始時刻に記録時の先頭イベントからの経過時間を足した時刻とする.
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = [0u8; INPUT_EVENT_SIZE];
+    let started = Instant::now();
+    let mut first: Option<Duration> = None;
+    loop {
+        match reader.read_exact(&mut buf) {
+            Ok(()) => {
+                let (timestamp, event) = parse_dump_event(&buf);
+                let first = *first.get_or_insert(timestamp);
+                let offset = timestamp.saturating_sub(first);
+                if let Some(key) = event_to_key(&event, started + offset) {
+                    if !send_key(key, &tx) {
+                        return Ok(());
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// `/dev/input/event*` からキー入力を読み取る入力ソース
+#[derive(Default)]
+pub struct EvdevSource {
+    paths: Vec<PathBuf>, // 読み込むデバイス、またはダンプファイル. 空の場合はキーボードを自動検出する
+}
+
+impl EvdevSource {
+    pub fn new() -> Self {
+        //! コンストラクタ. 接続されているキーボードを自動検出する.
+        Self::default()
+    }
+
+    pub fn with_paths(paths: Vec<PathBuf>) -> Self {
+        //! 読み込むデバイスを指定するコンストラクタ.
+        //! 通常のファイルを指定した場合は、記録済みのダンプファイルとして読み込む.
+        Self { paths }
+    }
+
+    pub fn is_available() -> bool {
+        //! 読み込み可能なキーボードが接続されているかどうかを返す
+        evdev::enumerate().any(|(_, device)| is_keyboard(&device))
+    }
+
+    fn spawn_reader(path: PathBuf, tx: mpsc::Sender<Key>) -> io::Result<thread::JoinHandle<()>> {
+        //! デバイス、またはダンプファイルを読み込むスレッドを起動する
+        let is_dump = path.metadata()?.is_file();
+        let device = if is_dump {
+            None
+        } else {
+            Some(Device::open(&path)?)
+        };
+
+        Ok(thread::spawn(move || {
+            let result = match device {
+                Some(device) => read_device(device, tx),
+                None => read_dump(&path, tx),
+            };
+            match result {
+                Ok(()) => info!("Finish reading {}", path.display()),
+                Err(e) => error!("Failed to read {}: {}", path.display(), e),
+            }
+        }))
+    }
+}
+
+impl InputSource for EvdevSource {
+    fn name(&self) -> &'static str {
+        "evdev"
+    }
+
+    fn run(&mut self, tx: mpsc::Sender<Key>) -> anyhow::Result<()> {
+        //! デバイスごとに読み込みスレッドを起動し、全てのスレッドが終了するまで待つ
+        let paths = if self.paths.is_empty() {
+            evdev::enumerate()
+                .filter(|(_, device)| is_keyboard(device))
+                .map(|(path, device)| {
+                    info!(
+                        "Found keyboard: {} ({})",
+                        path.display(),
+                        device.name().unwrap_or("unknown")
+                    );
+                    path
+                })
+                .collect()
+        } else {
+            self.paths.clone()
+        };
+
+        if paths.is_empty() {
+            bail!("No keyboard found in /dev/input. Is the user a member of the `input` group?");
+        }
+
+        let handles: Vec<_> = paths
+            .into_iter()
+            .filter_map(|path| match Self::spawn_reader(path.clone(), tx.clone()) {
+                Ok(handle) => Some(handle),
+                Err(e) => {
+                    warn!("Failed to open {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+
+        if handles.is_empty() {
+            bail!("Failed to open any keyboard device");
+        }
+
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("evdev reader thread panicked");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::keycode::VK_BACK;
+
+    // 64bit の little endian 環境で `cat /dev/input/eventX` により記録したダンプ.
+    // h, i をロールオーバーで入力し (h 押下, i 押下, h 解放, i 解放)、BackSpace を長押ししたもの.
+    // 各キーイベントの前後には MSC_SCAN と SYN_REPORT が含まれる.
+    const ROLLOVER_DUMP: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/evdev_rollover.bin"
+    );
+
+    #[test]
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    fn read_dump_converts_events_to_keys() {
+        //! ダンプファイルのキーイベントだけが、記録時の間隔を保った `Key` に変換されること
+        let (tx, rx) = mpsc::channel();
+        read_dump(Path::new(ROLLOVER_DUMP), tx).unwrap();
+        let keys: Vec<Key> = rx.iter().collect();
+
+        let actual: Vec<_> = keys
+            .iter()
+            .map(|key| (key.code, key.kind, key.repeat))
+            .collect();
+        let (h, i) = (b'H' as u32, b'I' as u32);
+        assert_eq!(
+            actual,
+            [
+                (h, KeyKind::Press, false),
+                (i, KeyKind::Press, false),
+                (h, KeyKind::Release, false),
+                (i, KeyKind::Release, false),
+                (VK_BACK, KeyKind::Press, false),
+                (VK_BACK, KeyKind::Press, true),
+                (VK_BACK, KeyKind::Press, true),
+                (VK_BACK, KeyKind::Release, false),
+            ]
+        );
+
+        let offsets: Vec<_> = keys
+            .iter()
+            .map(|key| key.time.duration_since(keys[0].time).as_millis())
+            .collect();
+        assert_eq!(offsets, [0, 80, 100, 150, 400, 900, 933, 950]);
+    }
+
+    #[test]
+    fn read_dump_fails_for_missing_file() {
+        //! 存在しないダンプファイルはエラーになること
+        let (tx, _rx) = mpsc::channel();
+        assert!(read_dump(Path::new("/nonexistent/evdev.bin"), tx).is_err());
+    }
+}
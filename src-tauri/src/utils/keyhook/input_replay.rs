@@ -0,0 +1,268 @@
+/**
+ * replay key input from a recorded file
+ */
+use super::recorder::CharClass;
+use super::{InputSource, Key, KeyKind};
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+
+/// キー入力記録ファイル (JSON Lines) の1行分
+///
+/// ```json
+/// {"t":1520,"code":8,"kind":"press","ime":false}
+/// {"t":1688,"code":65,"kind":"release","ime":false,"class":"letter","slot":1}
+/// {"t":2210,"code":8,"kind":"press","repeat":true,"ime":false}
+/// ```
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordedKey {
+    pub t: u64,        // 記録開始からの経過時間 [ms]
+    pub code: u32,     // 仮想キーコード (文字入力キーは、分類の代表のキーコード)
+    #[serde(default)]
+    pub kind: KeyKind, // 押下か解放か (省略時は押下)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repeat: bool,  // オートリピートによる押下かどうか (省略時はリピートでない)
+    pub ime: bool,     // IME変換中だったかどうか
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<CharClass>, // 文字入力キーの分類 (記録時に文字を伏せた場合のみ)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub slot: u8,      // 押下中の文字入力キーと直前に押下したキーを区別する番号 (省略時は 0)
+}
+
+fn is_zero(slot: &u8) -> bool {
+    //! 番号を省略できるかどうかを返す
+    *slot == 0
+}
+
+impl RecordedKey {
+    fn to_key(&self, started: Instant) -> Key {
+        //! `started` を記録開始時刻として `Key` に変換する.
+        //! 文字を伏せたキーは、番号ごとに分類内の別のキーコードとする.
+        let code = match self.class {
+            Some(class) => class.code(self.slot),
+            None => self.code,
+        };
+        Key::new(code, self.kind, self.ime)
+            .with_time(started + Duration::from_millis(self.t))
+            .with_repeat(self.repeat)
+    }
+}
+
+/// 記録ファイルのキー入力を、記録時と同じ間隔で再生する入力ソース.
+/// 再生速度に関わらず、`Key` の入力時刻は記録時の間隔を保つ.
+/// ミスタイプ率の評価と通知の判定も入力時刻に従って行うため、再生速度に関わらず同じ結果になる.
+pub struct ReplaySource {
+    path: PathBuf, // 記録ファイルのパス
+    speed: f64,    // 再生速度の倍率. 0の場合は待ち時間なしで再生する
+}
+
+impl ReplaySource {
+    pub fn new(path: PathBuf, speed: f64) -> Self {
+        //! コンストラクタ
+        Self { path, speed }
+    }
+
+    fn wait(&self, elapsed_ms: u64) {
+        //! 直前のキーからの経過時間を、再生速度に合わせて待つ
+        if self.speed > 0.0 && elapsed_ms > 0 {
+            let wait = Duration::try_from_secs_f64(elapsed_ms as f64 / 1000.0 / self.speed)
+                .unwrap_or(Duration::MAX);
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl InputSource for ReplaySource {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn is_replay(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, tx: mpsc::Sender<Key>) -> anyhow::Result<()> {
+        //! 記録ファイルを先頭から読み込み、全てのキーを送信すると戻る
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        let started = Instant::now();
+        let mut prev_t = None;
+        let mut count = 0usize;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let recorded: RecordedKey = serde_json::from_str(&line)
+                .with_context(|| format!("{}:{}: invalid record", self.path.display(), i + 1))?;
+
+            if let Some(prev_t) = prev_t {
+                self.wait(recorded.t.saturating_sub(prev_t));
+            }
+            prev_t = Some(recorded.t);
+
+            let key = recorded.to_key(started);
+            match tx.send(key) {
+                Ok(()) => debug!("Send key: {}", key.code),
+                Err(mpsc::SendError(e)) => {
+                    error!("Failed to send key: {}", e.code);
+                    break;
+                }
+            }
+            count += 1;
+        }
+
+        info!("Replayed {} keys from {}", count, self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::keycode::{VK_BACK, VK_OEM_COMMA};
+    use crate::utils::keyhook::recorder::Recorder;
+
+    use std::{fs, path::Path};
+
+    fn temp_path(name: &str) -> PathBuf {
+        //! テスト用の記録ファイルのパスを返す
+        std::env::temp_dir().join(format!(
+            "oopstime-replay-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn replay(path: &Path, speed: f64) -> (anyhow::Result<()>, Vec<Key>, Duration) {
+        //! 記録ファイルを再生し、結果と送信されたキー、再生にかかった時間を返す
+        let (tx, rx) = mpsc::channel();
+        let started = Instant::now();
+        let result = ReplaySource::new(path.to_path_buf(), speed).run(tx);
+        let elapsed = started.elapsed();
+        (result, rx.try_iter().collect(), elapsed)
+    }
+
+    #[test]
+    fn parses_recorded_key_with_defaults() {
+        //! 省略した項目は既定値とし、文字を伏せたキーは分類のキーコードに変換すること
+        let recorded: RecordedKey =
+            serde_json::from_str(r#"{"t":1520,"code":8,"ime":false}"#).unwrap();
+        assert_eq!(recorded.kind, KeyKind::Press);
+        assert!(!recorded.repeat);
+        assert_eq!(recorded.class, None);
+        assert_eq!(recorded.slot, 0);
+
+        let started = Instant::now();
+        let key = recorded.to_key(started);
+        assert_eq!(key.code, VK_BACK);
+        assert_eq!(key.time, started + Duration::from_millis(1520));
+
+        let recorded: RecordedKey = serde_json::from_str(
+            r#"{"t":1688,"code":65,"kind":"release","ime":true,"class":"letter","slot":1}"#,
+        )
+        .unwrap();
+        let key = recorded.to_key(started);
+        assert_eq!(key.kind, KeyKind::Release);
+        assert!(key.ime_composition);
+        assert_eq!(key.code, CharClass::Letter.code(1));
+        assert_ne!(key.code, recorded.code);
+    }
+
+    #[test]
+    fn replays_recorded_file() {
+        //! 記録したキー入力を、記録時の間隔と分類を保って再生すること
+        let path = temp_path("round-trip");
+        let mut recorder = Recorder::create(&path).unwrap();
+        let started = Instant::now();
+        let inputs = [
+            (b'Q' as u32, KeyKind::Press, 0),
+            (VK_OEM_COMMA, KeyKind::Press, 120),
+            (b'Q' as u32, KeyKind::Release, 150),
+            (VK_BACK, KeyKind::Press, 400),
+        ];
+        for (code, kind, ms) in inputs {
+            let key = Key::new(code, kind, false).with_time(started + Duration::from_millis(ms));
+            recorder.record(&key).unwrap();
+        }
+        drop(recorder);
+
+        let (result, keys, _) = replay(&path, 0.0);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(keys.len(), inputs.len());
+        // 文字は分類ごとに、押下中のキーを区別する番号のキーコードとなる
+        let codes: Vec<u32> = keys.iter().map(|key| key.code).collect();
+        let letter = CharClass::Letter.code(0);
+        assert_eq!(codes, [letter, CharClass::Punct.code(1), letter, VK_BACK]);
+        let kinds: Vec<KeyKind> = keys.iter().map(|key| key.kind).collect();
+        assert_eq!(kinds, inputs.map(|(_, kind, _)| kind));
+        // 記録開始から最初のキーまでの時間を除いて、間隔を保つ
+        let offsets: Vec<Duration> = keys.iter().map(|key| key.time - keys[0].time).collect();
+        let expected: Vec<Duration> = inputs
+            .iter()
+            .map(|(_, _, ms)| Duration::from_millis(*ms))
+            .collect();
+        for (offset, expected) in offsets.iter().zip(expected) {
+            assert!(
+                offset.abs_diff(expected) < Duration::from_millis(5),
+                "{:?}",
+                offsets
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_line_is_error() {
+        //! 不正な行がある場合は、行番号を含むエラーとし、それまでの行は再生すること
+        let path = temp_path("malformed");
+        fs::write(
+            &path,
+            "{\"t\":0,\"code\":8,\"ime\":false}\n\n{\"t\":100,\"code\":\n",
+        )
+        .unwrap();
+        let (result, keys, _) = replay(&path, 0.0);
+        fs::remove_file(&path).unwrap();
+        let error = result.unwrap_err();
+        assert!(
+            format!("{}", error).ends_with(":3: invalid record"),
+            "{}",
+            error
+        );
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn speed_scales_only_wait() {
+        //! 再生速度は待ち時間だけを変え、0 では待たずに再生し、キーの入力時刻は変えないこと
+        let path = temp_path("speed");
+        fs::write(
+            &path,
+            "{\"t\":0,\"code\":65,\"ime\":false}\n{\"t\":300,\"code\":66,\"ime\":false}\n",
+        )
+        .unwrap();
+        let (result, instant_keys, instant) = replay(&path, 0.0);
+        result.unwrap();
+        let (result, timed_keys, timed) = replay(&path, 1.0);
+        result.unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(instant < Duration::from_millis(150), "{:?}", instant);
+        assert!(timed >= Duration::from_millis(300), "{:?}", timed);
+        for keys in [instant_keys, timed_keys] {
+            assert_eq!(keys[1].time - keys[0].time, Duration::from_millis(300));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+/**
+ * keyboard hook for Windows
+ */
+use super::{composition, InputSource, Key, KeyKind};
+
+use std::{
+    sync::{mpsc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use log::{debug, error};
+
+use windows::Win32::{
+    Foundation::{LPARAM, LRESULT, WPARAM},
+    System::SystemInformation::GetTickCount,
+    UI::WindowsAndMessaging::{
+        CallNextHookEx, GetForegroundWindow, GetMessageW, SetWindowsHookExW, UnhookWindowsHookEx,
+        HHOOK, KBDLLHOOKSTRUCT, WH_KEYBOARD_LL, WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+    },
+};
+
+// `HHOOK` を `Send` にするためのラッパー型
+#[derive(Clone, Copy)]
+struct SafeHHook(HHOOK);
+
+unsafe impl Send for SafeHHook {}
+unsafe impl Sync for SafeHHook {}
+
+static HOOK: Mutex<Option<SafeHHook>> = Mutex::new(None);
+static TX: OnceLock<mpsc::Sender<Key>> = OnceLock::new();
+
+pub(super) fn event_time(tick: u32) -> Instant {
+    //! `KBDLLHOOKSTRUCT`, `MSLLHOOKSTRUCT` の時刻 (`GetTickCount` のミリ秒) を、現在の時刻からの差で `Instant` に変換する.
+    //! フックの呼び出しが遅れた場合も、チャタリングの判定などのためイベントの発生時刻とする.
+    let now = Instant::now();
+    let age = Duration::from_millis(unsafe { GetTickCount() }.wrapping_sub(tick) as u64);
+    now.checked_sub(age).unwrap_or(now)
+}
+
+unsafe extern "system" fn keyboard_proc(n_code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    if n_code >= 0 {
+        let kb_data: &KBDLLHOOKSTRUCT = &*(l_param.0 as *const KBDLLHOOKSTRUCT);
+
+        // Alt との同時押しなどは WM_SYSKEY* で通知される
+        let kind = match w_param.0 as u32 {
+            WM_KEYDOWN | WM_SYSKEYDOWN => Some(KeyKind::Press),
+            WM_KEYUP | WM_SYSKEYUP => Some(KeyKind::Release),
+            _ => None,
+        };
+
+        if let Some(kind) = kind {
+            let keycode = kb_data.vkCode;
+            let hwnd = GetForegroundWindow();
+            // KBDLLHOOKSTRUCT にはリピート回数が含まれないため、オートリピートの判定は KeyTracker で行う
+            // IME の変換状態は、Win32Composition が別スレッドで監視している
+            let key = Key::new(keycode, kind, composition::is_composing())
+                .with_time(event_time(kb_data.time))
+                .with_window(hwnd.0 as u64);
+
+            if let Some(tx) = TX.get() {
+                match tx.send(key) {
+                    Ok(()) => debug!("Send key: {}", key.code),
+                    Err(mpsc::SendError(e)) => error!("Failed to send key: {}", e.code),
+                }
+            }
+        }
+    }
+
+    let hook = *HOOK.lock().unwrap();
+    CallNextHookEx(hook.map(|h| h.0), n_code, w_param, l_param)
+}
+
+/// `SetWindowsHookExW` による低レベルキーボードフックの入力ソース
+#[derive(Default)]
+pub struct Win32Source;
+
+impl Win32Source {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self
+    }
+}
+
+impl InputSource for Win32Source {
+    fn name(&self) -> &'static str {
+        "win32"
+    }
+
+    fn run(&mut self, tx: mpsc::Sender<Key>) -> anyhow::Result<()> {
+        //! キーボードフックを登録し、メッセージループを回す.
+        //! メッセージループが終了すると、フックを解除して戻る.
+        TX.set(tx)
+            .map_err(|_| anyhow::anyhow!("win32 input source already running"))?;
+
+        unsafe {
+            // キーボードフック処理を登録
+            let hook: HHOOK = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), None, 0)
+                .context("Failed to set hook")?;
+            *HOOK.lock().unwrap() = Some(SafeHHook(hook));
+
+            let mut msg = std::mem::zeroed();
+            while GetMessageW(&mut msg, None, 0, 0).0 != 0 {
+                debug!("Received message: {}", msg.message);
+            }
+
+            // フックを解除
+            if let Some(hook) = HOOK.lock().unwrap().take() {
+                UnhookWindowsHookEx(hook.0).context("Failed to unhook")?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+/**
+ * keyboard input for Linux (X11 RECORD extension)
+ */
+use super::{composition, keycode, InputSource, Key, KeyKind};
+
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use log::{debug, error, info, warn};
+
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        record::{self, ConnectionExt as _},
+        xproto::{self, AtomEnum, ConnectionExt as _, Window},
+    },
+    rust_connection::RustConnection,
+    x11_utils::TryParse,
+};
+
+// RECORD 拡張の EnableContext の reply の category (プロトコル仕様で定義されている値)
+const RECORD_FROM_SERVER: u8 = 0;
+const RECORD_START_OF_DATA: u8 = 4;
+
+// コアプロトコルのイベントは常に 32 バイト
+const EVENT_SIZE: usize = 32;
+
+// X11 のキーコードは evdev のキーコードに 8 を足した値になっている
+const X11_KEYCODE_OFFSET: u8 = 8;
+
+/// `_NET_ACTIVE_WINDOW` からフォーカスされているウィンドウを取得する構造体
+struct FocusTracker<'a> {
+    conn: &'a RustConnection,
+    root: Window,
+    net_active_window: xproto::Atom,
+}
+
+impl<'a> FocusTracker<'a> {
+    fn new(conn: &'a RustConnection, screen_num: usize) -> anyhow::Result<Self> {
+        //! コンストラクタ
+        let root = conn.setup().roots[screen_num].root;
+        let net_active_window = conn
+            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+            .reply()
+            .context("Failed to intern _NET_ACTIVE_WINDOW")?
+            .atom;
+
+        Ok(Self {
+            conn,
+            root,
+            net_active_window,
+        })
+    }
+
+    fn active_window(&self) -> Option<Window> {
+        //! フォーカスされているウィンドウを返す.
+        //! ウィンドウマネージャが `_NET_ACTIVE_WINDOW` に対応していない場合は `None` を返す.
+        let reply = self
+            .conn
+            .get_property(false, self.root, self.net_active_window, AtomEnum::WINDOW, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        let window = reply.value32()?.next()?;
+        (window != x11rb::NONE).then_some(window)
+    }
+}
+
+// X サーバの時刻と受信した時刻の差がこれを超えた場合は、対応付けをやり直す
+const CLOCK_RESYNC: Duration = Duration::from_secs(1);
+
+/// X サーバがイベントに付けた時刻 (ミリ秒) を `Instant` に変換する構造体.
+/// まとめて受信したイベントも、チャタリングの判定などのため個別の時刻とする.
+#[derive(Default)]
+pub(super) struct ServerClock {
+    base: Option<(xproto::Timestamp, Instant)>, // 対応付けの基準とする、X サーバの時刻と受信した時刻
+}
+
+impl ServerClock {
+    pub fn instant_of(&mut self, time: xproto::Timestamp, now: Instant) -> Instant {
+        //! X サーバの時刻を変換する. 変換した時刻が受信した時刻 `now` より後になる場合や、
+        //! 大きく遅れる場合 (X サーバの時刻の一巡など) は、このイベントを基準に対応付けし直す.
+        if let Some((base_time, base)) = self.base {
+            let elapsed = Duration::from_millis(time.wrapping_sub(base_time) as u64);
+            let instant = base + elapsed;
+            if instant <= now && now.saturating_duration_since(instant) < CLOCK_RESYNC {
+                return instant;
+            }
+        }
+        self.base = Some((time, now));
+        now
+    }
+}
+
+/// X サーバのオートリピートを検出する構造体.
+/// X11 ではオートリピート中、同じ時刻の解放と押下が続けて通知されるため、
+/// 解放を次のイベントまで保留し、対になる押下が来た場合は解放を捨てて押下をリピートとする.
+#[derive(Default)]
+struct AutoRepeatFilter {
+    pending_release: Option<(xproto::Keycode, xproto::Timestamp, Key)>, // 保留中の解放
+}
+
+impl AutoRepeatFilter {
+    fn filter(&mut self, event: &xproto::KeyPressEvent, key: Key) -> [Option<Key>; 2] {
+        //! イベントを受け取り、送信すべき `Key` を古い順に返す
+        let pending = self.pending_release.take();
+        match key.kind {
+            KeyKind::Release => {
+                self.pending_release = Some((event.detail, event.time, key));
+                [pending.map(|(_, _, key)| key), None]
+            }
+            KeyKind::Press => match pending {
+                Some((detail, time, _)) if detail == event.detail && time == event.time => {
+                    [None, Some(key.with_repeat(true))]
+                }
+                _ => [pending.map(|(_, _, key)| key), Some(key)],
+            },
+        }
+    }
+}
+
+/// X11 の RECORD 拡張でキー入力を取得する入力ソース.
+/// `/dev/input` を読む権限がない環境でも、X サーバに接続できれば使用できる.
+#[derive(Default)]
+pub struct X11Source {
+    display: Option<String>, // 接続するディスプレイ. `None` の場合は環境変数 `DISPLAY`
+}
+
+impl X11Source {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    #[cfg(test)]
+    pub fn with_display(display: &str) -> Self {
+        //! 接続するディスプレイを指定するコンストラクタ (テスト用の X サーバに接続する)
+        Self {
+            display: Some(display.to_string()),
+        }
+    }
+
+    pub fn is_available() -> bool {
+        //! X サーバに接続できる環境かどうかを返す
+        std::env::var_os("DISPLAY").is_some()
+    }
+}
+
+fn event_to_key(
+    event: &xproto::KeyPressEvent,
+    kind: KeyKind,
+    time: Instant,
+    focus: &FocusTracker,
+) -> Option<Key> {
+    //! キー押下・解放イベントを `Key` に変換する
+    let code = keycode::from_evdev(event.detail.checked_sub(X11_KEYCODE_OFFSET)? as u16)?;
+
+    // X11 からは IME の状態を取得できないため、D-Bus で監視している変換状態を使用する
+    let key = Key::new(code, kind, composition::is_composing()).with_time(time);
+    Some(match focus.active_window() {
+        Some(window) => key.with_window(window as u64),
+        None => key,
+    })
+}
+
+/// RECORD 拡張で、全クライアントのデバイスイベントを記録する接続.
+/// 制御用とデータ受信用で、X サーバへの接続を2本使用する.
+pub(super) struct RecordConnection {
+    pub ctrl_conn: RustConnection, // 制御用の接続
+    data_conn: RustConnection,     // データ受信用の接続
+    pub screen_num: usize,         // 既定のスクリーン番号
+}
+
+impl RecordConnection {
+    pub fn connect(display: Option<&str>) -> anyhow::Result<Self> {
+        //! X サーバに接続し、RECORD 拡張に対応しているかどうかを確認する.
+        //! `display` が `None` の場合は、環境変数 `DISPLAY` のディスプレイに接続する.
+        let (ctrl_conn, screen_num) =
+            x11rb::connect(display).context("Failed to connect to X server")?;
+        let (data_conn, _) = x11rb::connect(display).context("Failed to connect to X server")?;
+
+        if ctrl_conn
+            .extension_information(record::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            bail!("X server does not support the RECORD extension");
+        }
+        ctrl_conn
+            .record_query_version(
+                record::X11_XML_VERSION.0 as _,
+                record::X11_XML_VERSION.1 as _,
+            )?
+            .reply()?;
+
+        Ok(Self {
+            ctrl_conn,
+            data_conn,
+            screen_num,
+        })
+    }
+
+    pub fn record(
+        &self,
+        first: u8,
+        last: u8,
+        mut on_event: impl FnMut(&[u8], Instant) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<()> {
+        //! 全クライアントの、イベントコード `first` から `last` までのデバイスイベントを記録し、
+        //! 記録されたイベント (32 バイト) と受信した時刻を `on_event` に渡し続ける.
+        //! `on_event` が `false` を返すと、記録を終了する.
+        let context = self.ctrl_conn.generate_id()?;
+        let empty = record::Range8 { first: 0, last: 0 };
+        let empty_ext = record::ExtRange {
+            major: empty,
+            minor: record::Range16 { first: 0, last: 0 },
+        };
+        let range = record::Range {
+            core_requests: empty,
+            core_replies: empty,
+            ext_requests: empty_ext,
+            ext_replies: empty_ext,
+            delivered_events: empty,
+            device_events: record::Range8 { first, last },
+            errors: empty,
+            client_started: false,
+            client_died: false,
+        };
+        self.ctrl_conn
+            .record_create_context(context, 0, &[record::CS::ALL_CLIENTS.into()], &[range])?
+            .check()
+            .context("Failed to create RECORD context")?;
+
+        for reply in self.data_conn.record_enable_context(context)? {
+            let reply = reply?;
+            if reply.client_swapped {
+                warn!("Byte swapped X11 clients are unsupported");
+                continue;
+            }
+
+            match reply.category {
+                RECORD_START_OF_DATA => info!("Start recording X11 device events"),
+                RECORD_FROM_SERVER => {
+                    let now = Instant::now();
+                    for data in reply.data.chunks_exact(EVENT_SIZE) {
+                        if !on_event(data, now)? {
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl InputSource for X11Source {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn run(&mut self, tx: mpsc::Sender<Key>) -> anyhow::Result<()> {
+        //! RECORD 拡張でキー押下・解放イベントのみを記録し、`Key` として送信し続ける
+        let conn = RecordConnection::connect(self.display.as_deref())?;
+        let focus = FocusTracker::new(&conn.ctrl_conn, conn.screen_num)?;
+        let mut repeat_filter = AutoRepeatFilter::default();
+        let mut clock = ServerClock::default();
+
+        conn.record(
+            xproto::KEY_PRESS_EVENT,
+            xproto::KEY_RELEASE_EVENT,
+            |data, now| {
+                let kind = match data[0] & 0x7f {
+                    xproto::KEY_PRESS_EVENT => KeyKind::Press,
+                    xproto::KEY_RELEASE_EVENT => KeyKind::Release,
+                    _ => return Ok(true),
+                };
+                // KeyReleaseEvent は KeyPressEvent と同じ構造
+                let (event, _) = xproto::KeyPressEvent::try_parse(data)?;
+                let time = clock.instant_of(event.time, now);
+                let Some(key) = event_to_key(&event, kind, time, &focus) else {
+                    return Ok(true);
+                };
+                for key in repeat_filter.filter(&event, key).into_iter().flatten() {
+                    match tx.send(key) {
+                        Ok(()) => debug!("Send key: {}", key.code),
+                        Err(mpsc::SendError(e)) => {
+                            error!("Failed to send key: {}", e.code);
+                            return Ok(false);
+                        }
+                    }
+                }
+                Ok(true)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        process::{Child, Command},
+        thread,
+    };
+    use x11rb::protocol::xtest::ConnectionExt as _;
+
+    // テスト用の X サーバのディスプレイ (実行中の X サーバと重ならない番号)
+    const TEST_DISPLAY: &str = ":97";
+
+    // evdev の KEY_A, KEY_B
+    const KEY_A: u8 = 30 + X11_KEYCODE_OFFSET;
+    const KEY_B: u8 = 48 + X11_KEYCODE_OFFSET;
+
+    /// テスト終了時に終了する Xvfb
+    struct Xvfb(Child);
+
+    impl Xvfb {
+        fn start() -> Self {
+            //! Xvfb を起動し、接続できるようになるまで待つ
+            let child = Command::new("Xvfb")
+                .args([TEST_DISPLAY, "-nolisten", "tcp"])
+                .spawn()
+                .expect("Failed to start Xvfb");
+            let xvfb = Self(child);
+            for _ in 0..50 {
+                if x11rb::connect(Some(TEST_DISPLAY)).is_ok() {
+                    return xvfb;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            panic!("Xvfb did not accept connections");
+        }
+    }
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn fake_key(conn: &RustConnection, type_: u8, detail: u8) {
+        //! XTEST 拡張でキー押下・解放を発生させる
+        conn.xtest_fake_input(type_, detail, x11rb::CURRENT_TIME, x11rb::NONE, 0, 0, 0)
+            .unwrap()
+            .check()
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires Xvfb; run with `cargo test -- --ignored`"]
+    fn records_key_events_under_xvfb() {
+        //! XTEST で発生させたキー押下・解放が、RECORD 拡張で記録され `Key` として送信されること
+        let _xvfb = Xvfb::start();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || X11Source::with_display(TEST_DISPLAY).run(tx));
+        let (conn, _) = x11rb::connect(Some(TEST_DISPLAY)).unwrap();
+
+        // 記録の開始前に発生させた入力は記録されないため、記録されるまで入力し直す
+        let first = (0..50)
+            .find_map(|_| {
+                fake_key(&conn, xproto::KEY_PRESS_EVENT, KEY_A);
+                fake_key(&conn, xproto::KEY_RELEASE_EVENT, KEY_A);
+                rx.recv_timeout(Duration::from_millis(200)).ok()
+            })
+            .expect("No key was recorded");
+        assert_eq!(
+            (first.code, first.kind, first.repeat),
+            (b'A' as u32, KeyKind::Press, false)
+        );
+
+        // 解放は次のイベントまで保留されるため、別のキーを押して送信させる
+        fake_key(&conn, xproto::KEY_PRESS_EVENT, KEY_B);
+        fake_key(&conn, xproto::KEY_RELEASE_EVENT, KEY_B);
+        let mut keys = Vec::new();
+        while let Ok(key) = rx.recv_timeout(Duration::from_secs(2)) {
+            keys.push((key.code, key.kind, key.repeat));
+            if key.code == b'B' as u32 {
+                break;
+            }
+        }
+        assert_eq!(keys.first(), Some(&(b'A' as u32, KeyKind::Release, false)));
+        assert_eq!(keys.last(), Some(&(b'B' as u32, KeyKind::Press, false)));
+    }
+}
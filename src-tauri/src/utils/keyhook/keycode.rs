@@ -0,0 +1,263 @@
+//! 仮想キーコードの定義
+//!
+//! `Key` のキーコードはプラットフォームに依らず、Windows の仮想キーコード (VK_*) の値で扱う.
+//! Windows 以外の入力ソースは、取得したキーコードをこの値に変換してから送信すること.
+//! 英字と数字のキーは ASCII コード ('A'..='Z', '0'..='9') と同じ値になる.
+
+// 定義表のため、入力ソースによっては使用しない定数も含む
+#![allow(dead_code)]
+
+use crate::utils::setting::KeyClassTable;
+
+pub const VK_BACK: u32 = 0x08;
+pub const VK_TAB: u32 = 0x09;
+pub const VK_RETURN: u32 = 0x0D;
+pub const VK_SHIFT: u32 = 0x10;
+pub const VK_CONTROL: u32 = 0x11;
+pub const VK_MENU: u32 = 0x12; // Alt
+pub const VK_PAUSE: u32 = 0x13;
+pub const VK_CAPITAL: u32 = 0x14;
+pub const VK_KANA: u32 = 0x15; // VK_HANGUL と同じ値
+pub const VK_HANJA: u32 = 0x19;
+pub const VK_ESCAPE: u32 = 0x1B;
+pub const VK_CONVERT: u32 = 0x1C;
+pub const VK_NONCONVERT: u32 = 0x1D;
+pub const VK_SPACE: u32 = 0x20;
+pub const VK_PRIOR: u32 = 0x21;
+pub const VK_NEXT: u32 = 0x22;
+pub const VK_END: u32 = 0x23;
+pub const VK_HOME: u32 = 0x24;
+pub const VK_LEFT: u32 = 0x25;
+pub const VK_UP: u32 = 0x26;
+pub const VK_RIGHT: u32 = 0x27;
+pub const VK_DOWN: u32 = 0x28;
+pub const VK_SNAPSHOT: u32 = 0x2C;
+pub const VK_INSERT: u32 = 0x2D;
+pub const VK_DELETE: u32 = 0x2E;
+pub const VK_LWIN: u32 = 0x5B;
+pub const VK_RWIN: u32 = 0x5C;
+pub const VK_APPS: u32 = 0x5D;
+pub const VK_NUMPAD0: u32 = 0x60;
+pub const VK_MULTIPLY: u32 = 0x6A;
+pub const VK_ADD: u32 = 0x6B;
+pub const VK_SEPARATOR: u32 = 0x6C;
+pub const VK_SUBTRACT: u32 = 0x6D;
+pub const VK_DECIMAL: u32 = 0x6E;
+pub const VK_DIVIDE: u32 = 0x6F;
+pub const VK_F1: u32 = 0x70;
+pub const VK_F13: u32 = 0x7C;
+pub const VK_NUMLOCK: u32 = 0x90;
+pub const VK_SCROLL: u32 = 0x91;
+pub const VK_LSHIFT: u32 = 0xA0;
+pub const VK_RSHIFT: u32 = 0xA1;
+pub const VK_LCONTROL: u32 = 0xA2;
+pub const VK_RCONTROL: u32 = 0xA3;
+pub const VK_LMENU: u32 = 0xA4;
+pub const VK_RMENU: u32 = 0xA5;
+pub const VK_OEM_1: u32 = 0xBA; // US: ;:  JP: :*
+pub const VK_OEM_PLUS: u32 = 0xBB;
+pub const VK_OEM_COMMA: u32 = 0xBC;
+pub const VK_OEM_MINUS: u32 = 0xBD;
+pub const VK_OEM_PERIOD: u32 = 0xBE;
+pub const VK_OEM_2: u32 = 0xBF; // US: /?
+pub const VK_OEM_3: u32 = 0xC0; // US: `~  JP: @`
+pub const VK_OEM_4: u32 = 0xDB; // US: [{
+pub const VK_OEM_5: u32 = 0xDC; // US: \|  JP: ¥|
+pub const VK_OEM_6: u32 = 0xDD; // US: ]}
+pub const VK_OEM_7: u32 = 0xDE; // US: '"
+pub const VK_OEM_102: u32 = 0xE2; // JP: \_
+pub const VK_OEM_COPY: u32 = 0xF2; // JP: カタカナ/ひらがな
+pub const VK_OEM_AUTO: u32 = 0xF3; // JP: 半角/全角
+
+pub const VK_Z: u32 = b'Z' as u32;
+
+pub const SHIFT_KEYS: [u32; 3] = [VK_SHIFT, VK_LSHIFT, VK_RSHIFT];
+pub const CONTROL_KEYS: [u32; 3] = [VK_CONTROL, VK_LCONTROL, VK_RCONTROL];
+pub const ALT_KEYS: [u32; 3] = [VK_MENU, VK_LMENU, VK_RMENU];
+pub const WIN_KEYS: [u32; 2] = [VK_LWIN, VK_RWIN];
+
+// AltGr (右 Alt). 多くの配列で記号やアクセント付きの文字の入力に使用するため、ショートカットの修飾キーとしない
+pub const VK_ALT_GR: u32 = VK_RMENU;
+
+pub fn is_modifier(code: u32) -> bool {
+    //! 修飾キー (Shift, Ctrl, Alt, Win) かどうかを返す
+    SHIFT_KEYS.contains(&code)
+        || CONTROL_KEYS.contains(&code)
+        || ALT_KEYS.contains(&code)
+        || WIN_KEYS.contains(&code)
+}
+
+pub fn is_navigation(code: u32) -> bool {
+    //! カーソル移動キー (矢印, Home, End, PageUp, PageDown) かどうかを返す
+    matches!(
+        code,
+        VK_LEFT | VK_UP | VK_RIGHT | VK_DOWN | VK_HOME | VK_END | VK_PRIOR | VK_NEXT
+    )
+}
+
+/// キーの分類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyClass {
+    Text,       // 文字入力 (英数字, 記号, 空白)
+    Navigation, // カーソル移動
+    Editing,    // 編集 (BackSpace, Delete, Insert, Enter, Tab)
+    Modifier,   // 修飾キー, ロックキー
+    Other,      // その他 (ファンクションキー, IME の切り替えなど)
+}
+
+pub fn default_class(code: u32) -> KeyClass {
+    //! 仮想キーコードの既定の分類を返す
+    match code {
+        c if is_modifier(c) => KeyClass::Modifier,
+        VK_CAPITAL | VK_NUMLOCK | VK_SCROLL => KeyClass::Modifier,
+        c if is_navigation(c) => KeyClass::Navigation,
+        VK_BACK | VK_DELETE | VK_INSERT | VK_RETURN | VK_TAB => KeyClass::Editing,
+        0x41..=0x5A | 0x30..=0x39 => KeyClass::Text, // 'A'..='Z', '0'..='9'
+        c if (VK_NUMPAD0..VK_NUMPAD0 + 10).contains(&c) => KeyClass::Text,
+        VK_OEM_1..=VK_OEM_3 | VK_OEM_4..=VK_OEM_7 | VK_OEM_102 => KeyClass::Text,
+        VK_MULTIPLY | VK_ADD | VK_SEPARATOR | VK_SUBTRACT | VK_DECIMAL | VK_DIVIDE => {
+            KeyClass::Text
+        }
+        VK_SPACE => KeyClass::Text,
+        _ => KeyClass::Other,
+    }
+}
+
+pub fn classify(code: u32, table: &KeyClassTable) -> KeyClass {
+    //! 設定の分類表に従って、仮想キーコードを分類する.
+    //! 分類表に含まれないキーは、既定の分類とする.
+    [
+        (&table.text, KeyClass::Text),
+        (&table.navigation, KeyClass::Navigation),
+        (&table.editing, KeyClass::Editing),
+        (&table.modifier, KeyClass::Modifier),
+        (&table.other, KeyClass::Other),
+    ]
+    .into_iter()
+    .find(|(codes, _)| codes.contains(&code))
+    .map_or_else(|| default_class(code), |(_, class)| class)
+}
+
+pub fn from_evdev(code: u16) -> Option<u32> {
+    //! Linux の evdev キーコード (KEY_*) を仮想キーコードに変換する.
+    //! X11 のキーコードは evdev のキーコードに 8 を足した値なので、8 を引いてから渡すこと.
+    //! 対応する仮想キーコードがない場合は `None` を返す.
+    let vk = match code {
+        1 => VK_ESCAPE, // KEY_ESC
+        2 => b'1' as u32, // KEY_1
+        3 => b'2' as u32, // KEY_2
+        4 => b'3' as u32, // KEY_3
+        5 => b'4' as u32, // KEY_4
+        6 => b'5' as u32, // KEY_5
+        7 => b'6' as u32, // KEY_6
+        8 => b'7' as u32, // KEY_7
+        9 => b'8' as u32, // KEY_8
+        10 => b'9' as u32, // KEY_9
+        11 => b'0' as u32, // KEY_0
+        12 => VK_OEM_MINUS, // KEY_MINUS
+        13 => VK_OEM_PLUS, // KEY_EQUAL
+        14 => VK_BACK, // KEY_BACKSPACE
+        15 => VK_TAB, // KEY_TAB
+        16 => b'Q' as u32, // KEY_Q
+        17 => b'W' as u32, // KEY_W
+        18 => b'E' as u32, // KEY_E
+        19 => b'R' as u32, // KEY_R
+        20 => b'T' as u32, // KEY_T
+        21 => b'Y' as u32, // KEY_Y
+        22 => b'U' as u32, // KEY_U
+        23 => b'I' as u32, // KEY_I
+        24 => b'O' as u32, // KEY_O
+        25 => b'P' as u32, // KEY_P
+        26 => VK_OEM_4, // KEY_LEFTBRACE
+        27 => VK_OEM_6, // KEY_RIGHTBRACE
+        28 | 96 => VK_RETURN, // KEY_ENTER / KEY_KPENTER
+        29 => VK_LCONTROL, // KEY_LEFTCTRL
+        30 => b'A' as u32, // KEY_A
+        31 => b'S' as u32, // KEY_S
+        32 => b'D' as u32, // KEY_D
+        33 => b'F' as u32, // KEY_F
+        34 => b'G' as u32, // KEY_G
+        35 => b'H' as u32, // KEY_H
+        36 => b'J' as u32, // KEY_J
+        37 => b'K' as u32, // KEY_K
+        38 => b'L' as u32, // KEY_L
+        39 => VK_OEM_1, // KEY_SEMICOLON
+        40 => VK_OEM_7, // KEY_APOSTROPHE
+        41 => VK_OEM_3, // KEY_GRAVE
+        42 => VK_LSHIFT, // KEY_LEFTSHIFT
+        43 => VK_OEM_5, // KEY_BACKSLASH
+        44 => b'Z' as u32, // KEY_Z
+        45 => b'X' as u32, // KEY_X
+        46 => b'C' as u32, // KEY_C
+        47 => b'V' as u32, // KEY_V
+        48 => b'B' as u32, // KEY_B
+        49 => b'N' as u32, // KEY_N
+        50 => b'M' as u32, // KEY_M
+        51 => VK_OEM_COMMA, // KEY_COMMA
+        52 => VK_OEM_PERIOD, // KEY_DOT
+        53 => VK_OEM_2, // KEY_SLASH
+        54 => VK_RSHIFT, // KEY_RIGHTSHIFT
+        55 => VK_MULTIPLY, // KEY_KPASTERISK
+        56 => VK_LMENU, // KEY_LEFTALT
+        57 => VK_SPACE, // KEY_SPACE
+        58 => VK_CAPITAL, // KEY_CAPSLOCK
+        59 => VK_F1, // KEY_F1
+        60 => VK_F1 + 1, // KEY_F2
+        61 => VK_F1 + 2, // KEY_F3
+        62 => VK_F1 + 3, // KEY_F4
+        63 => VK_F1 + 4, // KEY_F5
+        64 => VK_F1 + 5, // KEY_F6
+        65 => VK_F1 + 6, // KEY_F7
+        66 => VK_F1 + 7, // KEY_F8
+        67 => VK_F1 + 8, // KEY_F9
+        68 => VK_F1 + 9, // KEY_F10
+        69 => VK_NUMLOCK, // KEY_NUMLOCK
+        70 => VK_SCROLL, // KEY_SCROLLLOCK
+        71 => VK_NUMPAD0 + 7, // KEY_KP7
+        72 => VK_NUMPAD0 + 8, // KEY_KP8
+        73 => VK_NUMPAD0 + 9, // KEY_KP9
+        74 => VK_SUBTRACT, // KEY_KPMINUS
+        75 => VK_NUMPAD0 + 4, // KEY_KP4
+        76 => VK_NUMPAD0 + 5, // KEY_KP5
+        77 => VK_NUMPAD0 + 6, // KEY_KP6
+        78 => VK_ADD, // KEY_KPPLUS
+        79 => VK_NUMPAD0 + 1, // KEY_KP1
+        80 => VK_NUMPAD0 + 2, // KEY_KP2
+        81 => VK_NUMPAD0 + 3, // KEY_KP3
+        82 => VK_NUMPAD0, // KEY_KP0
+        83 => VK_DECIMAL, // KEY_KPDOT
+        85 => VK_OEM_AUTO, // KEY_ZENKAKUHANKAKU
+        86 | 89 => VK_OEM_102, // KEY_102ND / KEY_RO
+        87 => VK_F1 + 10, // KEY_F11
+        88 => VK_F1 + 11, // KEY_F12
+        92 => VK_CONVERT, // KEY_HENKAN
+        93 | 90 | 91 => VK_OEM_COPY, // KEY_KATAKANAHIRAGANA / KEY_KATAKANA / KEY_HIRAGANA
+        94 => VK_NONCONVERT, // KEY_MUHENKAN
+        97 => VK_RCONTROL, // KEY_RIGHTCTRL
+        98 => VK_DIVIDE, // KEY_KPSLASH
+        99 => VK_SNAPSHOT, // KEY_SYSRQ
+        100 => VK_RMENU, // KEY_RIGHTALT
+        102 => VK_HOME, // KEY_HOME
+        103 => VK_UP, // KEY_UP
+        104 => VK_PRIOR, // KEY_PAGEUP
+        105 => VK_LEFT, // KEY_LEFT
+        106 => VK_RIGHT, // KEY_RIGHT
+        107 => VK_END, // KEY_END
+        108 => VK_DOWN, // KEY_DOWN
+        109 => VK_NEXT, // KEY_PAGEDOWN
+        110 => VK_INSERT, // KEY_INSERT
+        111 => VK_DELETE, // KEY_DELETE
+        119 => VK_PAUSE, // KEY_PAUSE
+        121 => VK_SEPARATOR, // KEY_KPCOMMA
+        122 => VK_KANA, // KEY_HANGEUL
+        123 => VK_HANJA, // KEY_HANJA
+        124 => VK_OEM_5, // KEY_YEN
+        125 => VK_LWIN, // KEY_LEFTMETA
+        126 => VK_RWIN, // KEY_RIGHTMETA
+        127 => VK_APPS, // KEY_COMPOSE
+        183..=194 => VK_F13 + (code - 183) as u32, // KEY_F13 .. KEY_F24
+        _ => return None,
+    };
+
+    Some(vk)
+}
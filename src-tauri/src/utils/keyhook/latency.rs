@@ -0,0 +1,156 @@
+/**
+ * correction latency: how far the typing went before a correction and how long the correction was
+ */
+use super::correction::Verdict;
+use super::Key;
+use crate::utils::setting;
+
+use std::collections::VecDeque;
+
+use log::debug;
+use serde::Serialize;
+
+// 統計に使用する、直近の修正の連続の数
+const RECENT_RUNS: usize = 100;
+
+/// ミスタイプ修正の連続 (例えば、7文字入力した後に BackSpace を4回)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CorrectionRun {
+    pub typed: usize,  // 修正を始めるまでに入力した文字数
+    pub length: usize, // 連続したミスタイプ修正の回数
+}
+
+/// 直近の修正の連続の統計
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub runs: usize,                 // 統計に使用した修正の連続の数
+    pub typed_mean: f64,             // 修正を始めるまでに入力した文字数の平均
+    pub length_mean: f64,            // 連続したミスタイプ修正の回数の平均
+    pub last: Option<CorrectionRun>, // 最後に終わった修正の連続
+}
+
+/// ミスタイプ修正の連続を記録する構造体.
+/// 気付くのが遅れたミスタイプほど、多くの文字を消して打ち直すことになるため、
+/// 連続した修正の2回目以降は重みを加算する.
+/// ただし、修正を始めるまでに入力した文字数を超えて消した分は、それより前の入力の書き直しとして加算しない.
+#[derive(Default)]
+pub struct LatencyTracker {
+    typed: usize,                  // 直前の修正の連続の後に入力した文字数
+    run: Option<CorrectionRun>,    // 修正中の連続
+    runs: VecDeque<CorrectionRun>, // 直近の修正の連続
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    pub fn feed(&mut self, key: &Key, verdict: Verdict) -> f64 {
+        //! キー押下と判定結果を受け取り、ミスタイプ修正の重みに掛ける倍率を返す.
+        //! 倍率は、修正を始めるまでに `typed` 文字入力した連続の `n` 回目 (0始まり) の修正で
+        //! `1 + run_weight * min(n, typed - 1)` とし、`max_scale` を上限とする.
+        //! 例えば、7文字入力した後の4回の修正は 1, 1.25, 1.5, 1.75 倍、1文字入力した後の4回の修正は全て 1 倍とする.
+        match verdict {
+            Verdict::Mistype(_) => {
+                let typed = self.typed;
+                let run = self.run.get_or_insert(CorrectionRun { typed, length: 0 });
+                let config = setting::get_latency();
+                let late = run.length.min(run.typed.saturating_sub(1));
+                let scale = (1.0 + config.run_weight * late as f64).min(config.max_scale);
+                run.length += 1;
+                scale.max(1.0)
+            }
+            Verdict::Input => {
+                if let Some(run) = self.run.take() {
+                    debug!(
+                        "Correction run: typed {} chars, then {} corrections",
+                        run.typed, run.length
+                    );
+                    self.runs.push_back(run);
+                    if self.runs.len() > RECENT_RUNS {
+                        self.runs.pop_front();
+                    }
+                    self.typed = 0;
+                }
+                self.typed += key.is_text() as usize;
+                1.0
+            }
+            Verdict::Ignore => 1.0,
+        }
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        //! 直近の修正の連続の統計を返す
+        let runs = self.runs.len();
+        if runs == 0 {
+            return LatencyStats::default();
+        }
+        let (typed, length) = self.runs.iter().fold((0, 0), |(typed, length), run| {
+            (typed + run.typed, length + run.length)
+        });
+        LatencyStats {
+            runs,
+            typed_mean: typed as f64 / runs as f64,
+            length_mean: length as f64 / runs as f64,
+            last: self.runs.back().copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::{correction::Correction, KeyKind};
+
+    fn scales(typed: usize, corrections: usize) -> Vec<f64> {
+        //! `typed` 文字入力した後に `corrections` 回修正した場合の、各修正の倍率を返す
+        let mut tracker = LatencyTracker::new();
+        let key = Key::new(b'A' as u32, KeyKind::Press, false);
+        for _ in 0..typed {
+            assert_eq!(tracker.feed(&key, Verdict::Input), 1.0);
+        }
+        (0..corrections)
+            .map(|_| tracker.feed(&key, Verdict::Mistype(Correction::BackSpace)))
+            .collect()
+    }
+
+    #[test]
+    fn scale_grows_with_late_corrections() {
+        //! 入力した文字数の範囲で、連続した修正の2回目以降の倍率を加算する
+        assert_eq!(scales(7, 4), vec![1.0, 1.25, 1.5, 1.75]);
+        assert_eq!(scales(7, 8).last(), Some(&2.0));
+    }
+
+    #[test]
+    fn scale_ignores_corrections_beyond_typed() {
+        //! 修正を始めるまでに入力した文字数を超えた修正は加算しない
+        assert_eq!(scales(1, 4), vec![1.0; 4]);
+        assert_eq!(scales(3, 5), vec![1.0, 1.25, 1.5, 1.5, 1.5]);
+        assert_eq!(scales(0, 2), vec![1.0; 2]);
+    }
+
+    #[test]
+    fn run_is_recorded_on_next_input() {
+        //! 修正の連続は、次の文字入力で記録する
+        let mut tracker = LatencyTracker::new();
+        let key = Key::new(b'A' as u32, KeyKind::Press, false);
+        for _ in 0..7 {
+            tracker.feed(&key, Verdict::Input);
+        }
+        for _ in 0..4 {
+            tracker.feed(&key, Verdict::Mistype(Correction::BackSpace));
+        }
+        assert_eq!(tracker.stats().runs, 0);
+        tracker.feed(&key, Verdict::Input);
+        let stats = tracker.stats();
+        assert_eq!(stats.runs, 1);
+        assert_eq!(
+            stats.last,
+            Some(CorrectionRun {
+                typed: 7,
+                length: 4
+            })
+        );
+    }
+}
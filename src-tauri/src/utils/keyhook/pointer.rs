@@ -0,0 +1,195 @@
+/**
+ * pointer (mouse) input shared between pointer sources and the key history
+ */
+use super::activity;
+use crate::utils::setting;
+
+use std::{sync::Mutex, time::Instant};
+
+use log::debug;
+
+/// マウスの入力の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerKind {
+    Move,        // カーソルの移動
+    LeftPress,   // 左ボタンの押下
+    LeftRelease, // 左ボタンの解放
+    Other,       // その他のボタン, ホイール
+}
+
+/// マウスの入力
+#[derive(Clone, Copy, Debug)]
+pub struct PointerEvent {
+    pub kind: PointerKind, // 入力の種類
+    pub time: Instant,     // 入力時刻
+    pub x: i32,            // カーソルの位置 (入力ソースごとの座標系)
+    pub y: i32,
+}
+
+/// 文字入力の位置や選択範囲を変えるマウスの操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    Click,  // クリックでカーソルを移動した
+    Select, // ダブルクリックやドラッグで文字列を選択した
+}
+
+/// マウスの入力を取得し、`notify` で通知する入力ソース.
+/// キーボードの入力ソースとは別のスレッドで動作する.
+pub trait PointerSource: Send {
+    /// 入力ソースの名前 (ログ出力用)
+    fn name(&self) -> &'static str;
+
+    /// マウスの入力の取得を開始し、`notify` で通知し続ける.
+    /// 入力の取得を終了するか、エラーが発生すると戻る.
+    fn run(&mut self) -> anyhow::Result<()>;
+}
+
+/// マウスの入力の並びから、クリックと文字列の選択を判定する構造体
+#[derive(Default)]
+struct GestureTracker {
+    position: (i32, i32),                    // 現在のカーソルの位置
+    press: Option<(i32, i32)>,               // 左ボタンを押下した位置 (押下中のみ)
+    last_click: Option<(Instant, i32, i32)>, // 前回左ボタンを押下した時刻と位置 (ダブルクリックの判定用)
+    selected: bool,                          // 押下中のダブルクリックで文字列を選択したかどうか
+}
+
+impl GestureTracker {
+    fn is_near(&self, (x, y): (i32, i32)) -> bool {
+        //! 現在のカーソルの位置が、`(x, y)` から `drag_distance` 以内かどうかを返す
+        let distance = setting::get_pointer().drag_distance as i32;
+        (self.position.0 - x).abs() <= distance && (self.position.1 - y).abs() <= distance
+    }
+
+    fn feed(&mut self, event: &PointerEvent) -> Option<Gesture> {
+        //! マウスの入力を受け取り、クリックや文字列の選択が完了した場合はそれを返す
+        if event.kind != PointerKind::Other {
+            self.position = (event.x, event.y);
+        }
+        match event.kind {
+            PointerKind::LeftPress => {
+                // ダブルクリック (トリプルクリック) の2回目以降の押下で、単語 (行) が選択される
+                let double_click = setting::get_pointer().double_click.as_duration();
+                self.selected = self.last_click.is_some_and(|(time, x, y)| {
+                    event.time.saturating_duration_since(time) <= double_click
+                        && self.is_near((x, y))
+                });
+                self.press = Some(self.position);
+                self.last_click = Some((event.time, event.x, event.y));
+                self.selected.then_some(Gesture::Select)
+            }
+            PointerKind::LeftRelease => {
+                let press = self.press.take()?;
+                if self.selected {
+                    None
+                } else if self.is_near(press) {
+                    Some(Gesture::Click)
+                } else {
+                    Some(Gesture::Select)
+                }
+            }
+            PointerKind::Move | PointerKind::Other => None,
+        }
+    }
+}
+
+/// マウスの入力の状態. 入力ソースのスレッドから更新し、キー履歴のスレッドから参照する
+#[derive(Default)]
+struct PointerState {
+    tracker: GestureTracker,             // クリックと文字列の選択の判定
+    pending: Option<(Instant, Gesture)>, // キー入力にまだ反映していない操作
+}
+
+static STATE: Mutex<Option<PointerState>> = Mutex::new(None);
+
+pub fn notify(event: PointerEvent) {
+    //! マウスの入力を記録する
+    activity::touch(event.time);
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(PointerState::default);
+    if let Some(gesture) = state.tracker.feed(&event) {
+        debug!("Pointer gesture: {:?}", gesture);
+        state.pending = Some((event.time, gesture));
+    }
+}
+
+pub fn take_gesture(before: Instant) -> Option<Gesture> {
+    //! 時刻 `before` までに完了した、キー入力にまだ反映していない操作を返す.
+    //! 完了から `gesture_timeout` 以上経過した操作は、キー入力と関係ないものとして破棄する.
+    //! マウスの入力ソースが起動していない場合は、常に `None` を返す.
+    let mut state = STATE.lock().unwrap();
+    let state = state.as_mut()?;
+    let (time, gesture) = state.pending.filter(|(time, _)| *time <= before)?;
+    state.pending = None;
+    let age = before.saturating_duration_since(time);
+    if age >= setting::get_pointer().gesture_timeout.as_duration() {
+        debug!(
+            "Discard pointer gesture {:?}: {} ms old",
+            gesture,
+            age.as_millis()
+        );
+        return None;
+    }
+    Some(gesture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn event(kind: PointerKind, started: Instant, ms: u64, (x, y): (i32, i32)) -> PointerEvent {
+        //! 開始から `ms` ミリ秒後のマウスの入力を返す
+        PointerEvent {
+            kind,
+            time: started + Duration::from_millis(ms),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn click_double_click_and_drag() {
+        //! クリック、ダブルクリック、ドラッグを判定する
+        let started = Instant::now();
+        let mut tracker = GestureTracker::default();
+        let mut feed = |kind, ms, position| tracker.feed(&event(kind, started, ms, position));
+        assert_eq!(feed(PointerKind::LeftPress, 0, (10, 10)), None);
+        assert_eq!(
+            feed(PointerKind::LeftRelease, 80, (11, 10)),
+            Some(Gesture::Click)
+        );
+        assert_eq!(
+            feed(PointerKind::LeftPress, 250, (11, 11)),
+            Some(Gesture::Select)
+        );
+        assert_eq!(feed(PointerKind::LeftRelease, 300, (11, 11)), None);
+        assert_eq!(feed(PointerKind::LeftPress, 2000, (100, 100)), None);
+        assert_eq!(feed(PointerKind::Move, 2100, (180, 100)), None);
+        assert_eq!(
+            feed(PointerKind::LeftRelease, 2200, (180, 100)),
+            Some(Gesture::Select)
+        );
+    }
+
+    #[test]
+    fn pending_gesture_expires() {
+        //! キー入力に反映していない操作は、`gesture_timeout` 以上経過すると破棄する
+        let started = Instant::now();
+        let timeout = setting::get_pointer().gesture_timeout.as_duration();
+        let at = |ms: u64| started + Duration::from_millis(ms);
+        let click = |ms: u64| {
+            notify(event(PointerKind::LeftPress, started, ms, (0, 0)));
+            notify(event(PointerKind::LeftRelease, started, ms + 50, (0, 0)));
+        };
+
+        click(0);
+        assert_eq!(take_gesture(at(0)), None, "not completed yet");
+        assert_eq!(take_gesture(at(1000)), Some(Gesture::Click));
+        assert_eq!(take_gesture(at(1000)), None, "already taken");
+
+        click(10_000);
+        let expired = at(10_050) + timeout;
+        assert_eq!(take_gesture(expired), None);
+        assert_eq!(take_gesture(expired), None, "discarded");
+    }
+}
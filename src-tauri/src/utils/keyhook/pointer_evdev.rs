@@ -0,0 +1,145 @@
+/**
+ * mouse input for Linux (evdev)
+ */
+use super::input_evdev::event_time;
+use super::pointer::{self, PointerEvent, PointerKind, PointerSource};
+
+use std::{
+    io, thread,
+    time::{Instant, SystemTime},
+};
+
+use anyhow::bail;
+use evdev::{AbsoluteAxisCode, Device, EventType, InputEvent, KeyCode, RelativeAxisCode};
+use log::{error, info, warn};
+
+// evdev の EV_KEY イベントの value
+const KEY_VALUE_RELEASE: i32 = 0;
+const KEY_VALUE_PRESS: i32 = 1;
+
+fn is_pointer(device: &Device) -> bool {
+    //! マウスやタッチパッドなど、カーソルを動かすデバイスかどうかを返す
+    let has_left = device
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(KeyCode::BTN_LEFT));
+    let has_relative = device
+        .supported_relative_axes()
+        .is_some_and(|axes| axes.contains(RelativeAxisCode::REL_X));
+    let has_absolute = device
+        .supported_absolute_axes()
+        .is_some_and(|axes| axes.contains(AbsoluteAxisCode::ABS_X));
+    has_left && (has_relative || has_absolute)
+}
+
+/// デバイスのイベントから、カーソルの位置を追跡する構造体.
+/// マウスは移動量のみを通知するため、移動量を積算した仮想的な位置とする.
+#[derive(Default)]
+struct Cursor {
+    x: i32,
+    y: i32,
+}
+
+impl Cursor {
+    fn feed(&mut self, event: &InputEvent) -> Option<PointerKind> {
+        //! イベントを受け取り、マウスの入力の種類を返す. 関係しないイベントは `None` を返す.
+        let code = event.code();
+        match event.event_type() {
+            EventType::RELATIVE if code == RelativeAxisCode::REL_X.0 => {
+                self.x = self.x.wrapping_add(event.value());
+                Some(PointerKind::Move)
+            }
+            EventType::RELATIVE if code == RelativeAxisCode::REL_Y.0 => {
+                self.y = self.y.wrapping_add(event.value());
+                Some(PointerKind::Move)
+            }
+            EventType::RELATIVE => Some(PointerKind::Other), // ホイール
+            EventType::ABSOLUTE if code == AbsoluteAxisCode::ABS_X.0 => {
+                self.x = event.value();
+                Some(PointerKind::Move)
+            }
+            EventType::ABSOLUTE if code == AbsoluteAxisCode::ABS_Y.0 => {
+                self.y = event.value();
+                Some(PointerKind::Move)
+            }
+            EventType::KEY if code == KeyCode::BTN_LEFT.0 => match event.value() {
+                KEY_VALUE_PRESS => Some(PointerKind::LeftPress),
+                KEY_VALUE_RELEASE => Some(PointerKind::LeftRelease),
+                _ => None,
+            },
+            EventType::KEY if event.value() == KEY_VALUE_PRESS => Some(PointerKind::Other),
+            _ => None,
+        }
+    }
+}
+
+fn read_device(mut device: Device) -> io::Result<()> {
+    //! デバイスからイベントを読み続け、マウスの入力を通知する
+    let mut cursor = Cursor::default();
+    loop {
+        let events = device.fetch_events()?;
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        for event in events {
+            if let Some(kind) = cursor.feed(&event) {
+                pointer::notify(PointerEvent {
+                    kind,
+                    time: event_time(&event, now, wall),
+                    x: cursor.x,
+                    y: cursor.y,
+                });
+            }
+        }
+    }
+}
+
+/// `/dev/input/event*` からマウスやタッチパッドの入力を読み取る入力ソース.
+/// タッチパッドのタップによるクリックは libinput が合成するため、ボタンを押した場合のみ検出できる.
+#[derive(Default)]
+pub struct EvdevPointer;
+
+impl EvdevPointer {
+    pub fn new() -> Self {
+        //! コンストラクタ. 接続されているマウスを自動検出する.
+        Self
+    }
+
+    pub fn is_available() -> bool {
+        //! 読み込み可能なマウスが接続されているかどうかを返す
+        evdev::enumerate().any(|(_, device)| is_pointer(&device))
+    }
+}
+
+impl PointerSource for EvdevPointer {
+    fn name(&self) -> &'static str {
+        "evdev"
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        //! デバイスごとに読み込みスレッドを起動し、全てのスレッドが終了するまで待つ
+        let handles: Vec<_> = evdev::enumerate()
+            .filter(|(_, device)| is_pointer(device))
+            .map(|(path, device)| {
+                info!(
+                    "Found pointer: {} ({})",
+                    path.display(),
+                    device.name().unwrap_or("unknown")
+                );
+                thread::spawn(move || match read_device(device) {
+                    Ok(()) => info!("Finish reading {}", path.display()),
+                    Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+                })
+            })
+            .collect();
+
+        if handles.is_empty() {
+            bail!("No pointer device found in /dev/input");
+        }
+
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("evdev pointer reader thread panicked");
+            }
+        }
+
+        Ok(())
+    }
+}
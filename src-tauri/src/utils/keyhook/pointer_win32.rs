@@ -0,0 +1,80 @@
+/**
+ * mouse hook for Windows
+ */
+use super::input_win32::event_time;
+use super::pointer::{self, PointerEvent, PointerKind, PointerSource};
+
+use anyhow::Context;
+use log::debug;
+
+use windows::Win32::{
+    Foundation::{LPARAM, LRESULT, WPARAM},
+    UI::WindowsAndMessaging::{
+        CallNextHookEx, GetMessageW, SetWindowsHookExW, UnhookWindowsHookEx, MSLLHOOKSTRUCT,
+        WH_MOUSE_LL, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MOUSEHWHEEL, WM_MOUSEMOVE,
+        WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_XBUTTONDOWN,
+    },
+};
+
+unsafe extern "system" fn mouse_proc(n_code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    if n_code >= 0 {
+        let ms_data: &MSLLHOOKSTRUCT = &*(l_param.0 as *const MSLLHOOKSTRUCT);
+
+        let kind = match w_param.0 as u32 {
+            WM_MOUSEMOVE => Some(PointerKind::Move),
+            WM_LBUTTONDOWN => Some(PointerKind::LeftPress),
+            WM_LBUTTONUP => Some(PointerKind::LeftRelease),
+            WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN | WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+                Some(PointerKind::Other)
+            }
+            _ => None,
+        };
+
+        if let Some(kind) = kind {
+            pointer::notify(PointerEvent {
+                kind,
+                time: event_time(ms_data.time),
+                x: ms_data.pt.x,
+                y: ms_data.pt.y,
+            });
+        }
+    }
+
+    // 低レベルフックでは、フックのハンドルは使用されない
+    CallNextHookEx(None, n_code, w_param, l_param)
+}
+
+/// `SetWindowsHookExW` による低レベルマウスフックの入力ソース
+#[derive(Default)]
+pub struct Win32Pointer;
+
+impl Win32Pointer {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self
+    }
+}
+
+impl PointerSource for Win32Pointer {
+    fn name(&self) -> &'static str {
+        "win32"
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        //! マウスフックを登録し、メッセージループを回す.
+        //! メッセージループが終了すると、フックを解除して戻る.
+        unsafe {
+            let hook = SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_proc), None, 0)
+                .context("Failed to set mouse hook")?;
+
+            let mut msg = std::mem::zeroed();
+            while GetMessageW(&mut msg, None, 0, 0).0 != 0 {
+                debug!("Received message: {}", msg.message);
+            }
+
+            UnhookWindowsHookEx(hook).context("Failed to unhook mouse hook")?;
+        }
+
+        Ok(())
+    }
+}
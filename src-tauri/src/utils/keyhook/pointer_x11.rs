@@ -0,0 +1,57 @@
+/**
+ * mouse input for Linux (X11 RECORD extension)
+ */
+use super::input_x11::{RecordConnection, ServerClock};
+use super::pointer::{self, PointerEvent, PointerKind, PointerSource};
+
+use x11rb::{protocol::xproto, x11_utils::TryParse};
+
+// X11 のマウスボタンの番号 (4 以降はホイール)
+const BUTTON_LEFT: xproto::Button = 1;
+
+/// X11 の RECORD 拡張でマウスの入力を取得する入力ソース.
+/// キー入力と同じく、X サーバに接続できれば `/dev/input` を読む権限がなくても使用できる.
+#[derive(Default)]
+pub struct X11Pointer;
+
+impl X11Pointer {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self
+    }
+}
+
+impl PointerSource for X11Pointer {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        //! RECORD 拡張でボタンの押下・解放とカーソルの移動を記録し、通知し続ける
+        let conn = RecordConnection::connect(None)?;
+        let mut clock = ServerClock::default();
+
+        conn.record(
+            xproto::BUTTON_PRESS_EVENT,
+            xproto::MOTION_NOTIFY_EVENT,
+            |data, now| {
+                // ButtonPressEvent, ButtonReleaseEvent, MotionNotifyEvent は同じ構造
+                let (event, _) = xproto::ButtonPressEvent::try_parse(data)?;
+                let kind = match (data[0] & 0x7f, event.detail) {
+                    (xproto::MOTION_NOTIFY_EVENT, _) => PointerKind::Move,
+                    (xproto::BUTTON_PRESS_EVENT, BUTTON_LEFT) => PointerKind::LeftPress,
+                    (xproto::BUTTON_RELEASE_EVENT, BUTTON_LEFT) => PointerKind::LeftRelease,
+                    (xproto::BUTTON_PRESS_EVENT, _) => PointerKind::Other,
+                    _ => return Ok(true),
+                };
+                pointer::notify(PointerEvent {
+                    kind,
+                    time: clock.instant_of(event.time, now),
+                    x: event.root_x as i32,
+                    y: event.root_y as i32,
+                });
+                Ok(true)
+            },
+        )
+    }
+}
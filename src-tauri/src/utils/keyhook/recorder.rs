@@ -0,0 +1,315 @@
+/**
+ * privacy-safe key input recorder
+ */
+use super::input_replay::RecordedKey;
+use super::keycode::*;
+use super::{Key, KeyKind};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// 文字入力キーの分類.
+/// 記録ファイルには入力した文字そのものではなく、この分類だけを保存する.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Letter,
+    Digit,
+    Punct,
+    Space,
+}
+
+impl CharClass {
+    fn of(code: u32) -> Option<Self> {
+        //! 仮想キーコードの分類を返す. 文字入力キーでない場合は `None` を返す.
+        match code {
+            0x41..=0x5A => Some(Self::Letter), // 'A'..='Z'
+            0x30..=0x39 => Some(Self::Digit),  // '0'..='9'
+            c if (VK_NUMPAD0..VK_NUMPAD0 + 10).contains(&c) => Some(Self::Digit),
+            VK_OEM_1..=VK_OEM_3 | VK_OEM_4..=VK_OEM_7 | VK_OEM_102 => Some(Self::Punct),
+            VK_MULTIPLY | VK_ADD | VK_SEPARATOR | VK_SUBTRACT | VK_DECIMAL | VK_DIVIDE => {
+                Some(Self::Punct)
+            }
+            VK_SPACE => Some(Self::Space),
+            _ => None,
+        }
+    }
+
+    fn canonical(self) -> u32 {
+        //! 分類を代表する仮想キーコードを返す
+        match self {
+            Self::Letter => 0x41, // 'A'
+            Self::Digit => 0x30,  // '0'
+            Self::Punct => VK_OEM_PERIOD,
+            Self::Space => VK_SPACE,
+        }
+    }
+
+    pub fn code(self, slot: u8) -> u32 {
+        //! 再生時に使用する仮想キーコードを返す.
+        //! 押下中のキーを区別するため、`slot` ごとに分類内の別のキーコードとする (0 は代表のキーコード).
+        let codes: Vec<u32> = (0..=0xFF)
+            .filter(|code| Self::of(*code) == Some(self))
+            .collect();
+        let first = codes
+            .iter()
+            .position(|code| *code == self.canonical())
+            .unwrap_or(0);
+        codes[(first + slot as usize) % codes.len()]
+    }
+}
+
+/// キー入力を記録ファイル (JSON Lines) に保存する構造体.
+/// 文字入力キーは分類 (英字/数字/記号/空白) の代表のキーコードに置き換えるため、入力内容は保存されない.
+/// ロールオーバーや同じキーの連打を再生できるように、押下ごとに、押下中のキーと直前に押したキーを区別する番号だけを保存する.
+/// 番号は同じキーでも押下ごとに変わるため、番号の並びから入力内容は分からない.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: Instant,        // 記録開始時刻
+    held: HashMap<u32, u8>,  // 押下中の文字入力キーと、その番号
+    last: Option<(u32, u8)>, // 直前に押下した文字入力キーと、その番号
+    shortcuts: HashSet<u32>, // ショートカットキーの操作として押下中のキー
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        //! 記録ファイルを作成する. 既に存在する場合は上書きする.
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+            held: HashMap::new(),
+            last: None,
+            shortcuts: HashSet::new(),
+        })
+    }
+
+    fn is_shortcut(&mut self, key: &Key) -> bool {
+        //! キーをショートカットキーの操作として押下したかどうかを返す.
+        //! 押下と解放のキーコードを揃えるため、解放とオートリピートは最初の押下の判定に従う.
+        match key.kind {
+            KeyKind::Press if key.repeat => self.shortcuts.contains(&key.code),
+            KeyKind::Press if key.modifiers.is_shortcut() => {
+                self.shortcuts.insert(key.code);
+                true
+            }
+            KeyKind::Press => {
+                self.shortcuts.remove(&key.code);
+                false
+            }
+            KeyKind::Release => self.shortcuts.remove(&key.code),
+        }
+    }
+
+    fn slot(&mut self, key: &Key) -> u8 {
+        //! 文字入力キーの押下・解放に、押下中のキーを区別する番号を割り当てる.
+        //! 直前に押下したキーと同じキーは同じ番号、別のキーは直前のキーとも押下中のキーとも異なる番号とする.
+        match key.kind {
+            KeyKind::Release => self.held.remove(&key.code).unwrap_or(0),
+            KeyKind::Press => {
+                if let Some(slot) = self.held.get(&key.code) {
+                    return *slot;
+                }
+                let slot = match self.last {
+                    Some((code, slot)) if code == key.code => slot,
+                    last => (0..=u8::MAX)
+                        .find(|slot| {
+                            last.is_none_or(|(_, last)| last != *slot)
+                                && !self.held.values().any(|held| held == slot)
+                        })
+                        .unwrap_or(0),
+                };
+                self.held.insert(key.code, slot);
+                self.last = Some((key.code, slot));
+                slot
+            }
+        }
+    }
+
+    pub fn record(&mut self, key: &Key) -> anyhow::Result<()> {
+        //! キー入力を1件記録する.
+        //! Ctrl+Z などのショートカットは文字の入力ではなく、再生時の判定にキーコードが必要なため、伏せずに記録する.
+        //! AltGr による文字の入力はショートカットに含まれないため、伏せて記録する.
+        let class = if self.is_shortcut(key) {
+            None
+        } else {
+            CharClass::of(key.code)
+        };
+        let (code, slot) = match class {
+            Some(class) => (class.canonical(), self.slot(key)),
+            None => (key.code, 0),
+        };
+        let recorded = RecordedKey {
+            t: key.time.saturating_duration_since(self.started).as_millis() as u64,
+            code,
+            kind: key.kind,
+            repeat: key.repeat,
+            ime: key.ime_composition,
+            class,
+            slot,
+        };
+
+        serde_json::to_writer(&mut self.writer, &recorded)?;
+        self.writer.write_all(b"\n")?;
+        // 異常終了しても直前までの記録が残るように、1件ごとに書き出す
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::tracker::KeyTracker;
+    use crate::utils::setting::KeyClassTable;
+
+    use std::{fs, time::Duration};
+
+    fn record(name: &str, inputs: &[(u32, KeyKind, u64)]) -> Vec<RecordedKey> {
+        //! キー入力の並びを記録し、記録ファイルの内容を返す
+        let path = std::env::temp_dir().join(format!(
+            "oopstime-recorder-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let mut recorder = Recorder::create(&path).unwrap();
+        let mut tracker = KeyTracker::new();
+        for (code, kind, ms) in inputs {
+            let time = recorder.started + Duration::from_millis(*ms);
+            let key = tracker.track(
+                Key::new(*code, *kind, false).with_time(time),
+                &KeyClassTable::default(),
+            );
+            recorder.record(&key).unwrap();
+        }
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn slots(recorded: &[RecordedKey]) -> Vec<u8> {
+        //! 記録されたキーの番号を返す
+        recorded.iter().map(|key| key.slot).collect()
+    }
+
+    #[test]
+    fn letters_are_recorded_as_canonical_code() {
+        //! 文字入力キーは、入力した文字に依らず分類の代表のキーコードで記録されること
+        let recorded = record(
+            "canonical",
+            &[
+                (b'Q' as u32, KeyKind::Press, 0),
+                (b'Q' as u32, KeyKind::Release, 50),
+                (b'7' as u32, KeyKind::Press, 100),
+                (VK_OEM_COMMA, KeyKind::Press, 200),
+                (VK_SPACE, KeyKind::Press, 300),
+            ],
+        );
+        let recorded: Vec<(u32, Option<CharClass>)> =
+            recorded.iter().map(|key| (key.code, key.class)).collect();
+        assert_eq!(
+            recorded,
+            [
+                (b'A' as u32, Some(CharClass::Letter)),
+                (b'A' as u32, Some(CharClass::Letter)),
+                (b'0' as u32, Some(CharClass::Digit)),
+                (VK_OEM_PERIOD, Some(CharClass::Punct)),
+                (VK_SPACE, Some(CharClass::Space)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rollover_keys_get_distinct_slots() {
+        //! ロールオーバーで押下中の別々のキーは別の番号で記録され、解放は押下と同じ番号になること
+        let (h, e) = (b'H' as u32, b'E' as u32);
+        let recorded = record(
+            "rollover",
+            &[
+                (h, KeyKind::Press, 0),
+                (e, KeyKind::Press, 40),
+                (h, KeyKind::Release, 60),
+                (e, KeyKind::Release, 90),
+            ],
+        );
+        assert_eq!(slots(&recorded), [0, 1, 0, 1]);
+        let replayed: Vec<u32> = recorded
+            .iter()
+            .map(|key| key.class.unwrap().code(key.slot))
+            .collect();
+        assert_ne!(replayed[0], replayed[1]);
+        assert_eq!(
+            replayed,
+            [replayed[0], replayed[1], replayed[0], replayed[1]]
+        );
+    }
+
+    #[test]
+    fn slots_tell_only_whether_the_same_key_was_pressed_again() {
+        //! 同じキーの連打は同じ番号、別のキーは直前と異なる番号となり、キーごとの番号は固定されないこと
+        let tap = |code: u8, ms: u64| {
+            [
+                (code as u32, KeyKind::Press, ms),
+                (code as u32, KeyKind::Release, ms + 30),
+            ]
+        };
+        let inputs: Vec<(u32, KeyKind, u64)> = [
+            tap(b'A', 0),
+            tap(b'A', 100),
+            tap(b'B', 200),
+            tap(b'C', 300),
+            tap(b'A', 400),
+        ]
+        .concat();
+        let recorded = record("hammer", &inputs);
+        let presses: Vec<u8> = slots(&recorded).into_iter().step_by(2).collect();
+        assert_eq!(presses, [0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn records_auto_repeat() {
+        //! オートリピートによる押下が記録されること
+        let recorded = record(
+            "repeat",
+            &[
+                (VK_BACK, KeyKind::Press, 0),
+                (VK_BACK, KeyKind::Press, 500),
+                (VK_BACK, KeyKind::Release, 540),
+            ],
+        );
+        let repeats: Vec<bool> = recorded.iter().map(|key| key.repeat).collect();
+        assert_eq!(repeats, [false, true, false]);
+    }
+
+    #[test]
+    fn records_shortcut_codes_but_hides_alt_gr_characters() {
+        //! ショートカットのキーコードは伏せず、AltGr による文字の入力は伏せて記録すること.
+        //! ショートカットとして押したキーは、修飾キーを先に離しても解放を伏せずに記録すること
+        let recorded = record(
+            "shortcut",
+            &[
+                (VK_LCONTROL, KeyKind::Press, 0),
+                (VK_Z, KeyKind::Press, 10),
+                (VK_LCONTROL, KeyKind::Release, 20),
+                (VK_Z, KeyKind::Release, 30),
+                (VK_RMENU, KeyKind::Press, 100),
+                (b'Q' as u32, KeyKind::Press, 110),
+            ],
+        );
+        assert_eq!((recorded[1].code, recorded[1].class), (VK_Z, None));
+        assert_eq!((recorded[3].code, recorded[3].class), (VK_Z, None));
+        assert_eq!(recorded[5].class, Some(CharClass::Letter));
+    }
+}
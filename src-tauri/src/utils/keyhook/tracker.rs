@@ -0,0 +1,247 @@
+/**
+ * key state tracking shared by all input sources
+ */
+use super::keycode::{
+    self, ALT_KEYS, CONTROL_KEYS, SHIFT_KEYS, VK_ALT_GR, VK_CONTROL, VK_LCONTROL, WIN_KEYS,
+};
+use super::{Key, KeyKind, Modifiers};
+use crate::utils::setting::KeyClassTable;
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// 解放を受信できなかったキーを押下中とみなし続ける最大時間.
+// 画面ロックなどでキー解放が入力ソースに届かない場合に、修飾キーが押されたままになるのを防ぐ.
+const STALE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 押下中のキーの状態
+struct Pressed {
+    since: Instant,     // 最初に押下した時刻
+    last_seen: Instant, // 最後に押下 (オートリピートを含む) を受信した時刻
+}
+
+/// 押下中のキーを追跡し、入力ソースから受信した `Key` に押下時間と修飾キーの状態を補う構造体
+#[derive(Default)]
+pub struct KeyTracker {
+    pressed: HashMap<u32, Pressed>, // 押下中のキー
+}
+
+impl KeyTracker {
+    pub fn new() -> Self {
+        //! コンストラクタ
+        Self::default()
+    }
+
+    fn is_pressed(&self, codes: &[u32]) -> bool {
+        //! `codes` のいずれかが押下中かどうかを返す
+        codes.iter().any(|code| self.pressed.contains_key(code))
+    }
+
+    fn is_modifier_pressed(&self, codes: &[u32], alt_gr: bool) -> bool {
+        //! AltGr を除いて、`codes` のいずれかが押下中かどうかを返す.
+        //! Windows では AltGr を押すと左 Ctrl の押下も通知されるため、AltGr と同時に押下中の左 Ctrl も除く.
+        codes
+            .iter()
+            .filter(|code| **code != VK_ALT_GR)
+            .filter(|code| !(alt_gr && matches!(**code, VK_CONTROL | VK_LCONTROL)))
+            .any(|code| self.pressed.contains_key(code))
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        //! 現在の修飾キーの状態を返す.
+        //! AltGr は文字の入力に使用するため、Alt や Ctrl としては扱わない.
+        let alt_gr = self.is_pressed(&[VK_ALT_GR]);
+        Modifiers {
+            shift: self.is_pressed(&SHIFT_KEYS),
+            ctrl: self.is_modifier_pressed(&CONTROL_KEYS, alt_gr),
+            alt: self.is_modifier_pressed(&ALT_KEYS, alt_gr),
+            win: self.is_pressed(&WIN_KEYS),
+        }
+    }
+
+    pub fn track(&mut self, mut key: Key, classes: &KeyClassTable) -> Key {
+        //! キーの押下状態を更新し、分類表 `classes` によるキーの分類と、修飾キーの状態と、解放時には押下時間を設定して返す.
+        //! 押下中のキーが再度押下された場合は、オートリピートとして設定する.
+        //! オートリピートで押下が続いた場合も、押下時間は最初の押下からの時間とする.
+        self.pressed
+            .retain(|_, pressed| key.time.saturating_duration_since(pressed.last_seen) < STALE_TIMEOUT);
+        key.class = keycode::classify(key.code, classes);
+        key.modifiers = self.modifiers();
+
+        match key.kind {
+            KeyKind::Press => match self.pressed.get_mut(&key.code) {
+                Some(pressed) => {
+                    // 解放されないまま再度押下された場合はオートリピートとみなす.
+                    // 低レベルキーボードフックなど、リピート情報を持たない入力ソースのための判定.
+                    pressed.last_seen = key.time;
+                    key = key.with_repeat(true);
+                }
+                None => {
+                    self.pressed.insert(
+                        key.code,
+                        Pressed {
+                            since: key.time,
+                            last_seen: key.time,
+                        },
+                    );
+                }
+            },
+            KeyKind::Release => {
+                key.hold = self
+                    .pressed
+                    .remove(&key.code)
+                    .map(|pressed| key.time.saturating_duration_since(pressed.since));
+            }
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::keyhook::keycode::{VK_BACK, VK_LMENU, VK_RMENU, VK_Z};
+
+    /// 一定の時刻から始まるキー入力の並びを作る
+    struct Timeline {
+        tracker: KeyTracker,
+        started: Instant,
+    }
+
+    impl Timeline {
+        fn new() -> Self {
+            Self {
+                tracker: KeyTracker::new(),
+                started: Instant::now(),
+            }
+        }
+
+        fn input(&mut self, code: u32, kind: KeyKind, ms: u64) -> Key {
+            //! 開始から `ms` ミリ秒後のキー入力を追跡する
+            let time = self.started + Duration::from_millis(ms);
+            self.tracker.track(
+                Key::new(code, kind, false).with_time(time),
+                &KeyClassTable::default(),
+            )
+        }
+
+        fn press(&mut self, code: u32, ms: u64) -> Key {
+            self.input(code, KeyKind::Press, ms)
+        }
+
+        fn release(&mut self, code: u32, ms: u64) -> Key {
+            self.input(code, KeyKind::Release, ms)
+        }
+    }
+
+    #[test]
+    fn tapped_key_has_hold_duration() {
+        //! 押下して離したキーは、解放時に押下時間が設定され、リピートとしないこと
+        let mut timeline = Timeline::new();
+        let press = timeline.press(b'A' as u32, 0);
+        assert!(!press.repeat && press.hold.is_none());
+        let release = timeline.release(b'A' as u32, 80);
+        assert_eq!(release.hold, Some(Duration::from_millis(80)));
+
+        // 離した後に再度押下した場合は、新しい押下とする
+        let press = timeline.press(b'A' as u32, 200);
+        assert!(!press.repeat);
+    }
+
+    #[test]
+    fn held_key_is_inferred_as_auto_repeat() {
+        //! 離さずに再度押下されたキーはオートリピートとし、押下時間は最初の押下からの時間とすること
+        let mut timeline = Timeline::new();
+        assert!(!timeline.press(VK_BACK, 0).repeat);
+        assert!(timeline.press(VK_BACK, 500).repeat);
+        assert!(timeline.press(VK_BACK, 533).repeat);
+        let release = timeline.release(VK_BACK, 550);
+        assert!(!release.repeat);
+        assert_eq!(release.hold, Some(Duration::from_millis(550)));
+    }
+
+    #[test]
+    fn rollover_is_not_auto_repeat() {
+        //! 前のキーを離す前に次のキーを押下しても、別のキーはリピートとしないこと
+        let mut timeline = Timeline::new();
+        assert!(!timeline.press(b'H' as u32, 0).repeat);
+        assert!(!timeline.press(b'E' as u32, 40).repeat);
+        assert!(timeline.release(b'H' as u32, 60).hold.is_some());
+        assert!(timeline.release(b'E' as u32, 90).hold.is_some());
+    }
+
+    #[test]
+    fn source_reported_repeat_is_kept() {
+        //! 入力ソースがリピートと通知した押下は、そのままリピートとすること
+        let mut timeline = Timeline::new();
+        let time = timeline.started;
+        let key = timeline.tracker.track(
+            Key::new(b'A' as u32, KeyKind::Press, false)
+                .with_time(time)
+                .with_repeat(true),
+            &KeyClassTable::default(),
+        );
+        assert!(key.repeat);
+    }
+
+    #[test]
+    fn stale_key_expires() {
+        //! 解放を受信できなかったキーは、`STALE_TIMEOUT` 後に押下中とみなさないこと
+        let stale = STALE_TIMEOUT.as_millis() as u64;
+        let mut timeline = Timeline::new();
+        timeline.press(VK_LCONTROL, 0);
+        assert!(timeline.press(VK_Z, 10).modifiers.ctrl);
+        timeline.release(VK_Z, 20);
+
+        // 期限内は押下中のまま
+        assert!(timeline.press(VK_Z, stale - 10).modifiers.ctrl);
+        timeline.release(VK_Z, stale - 5);
+
+        // Ctrl の押下から `STALE_TIMEOUT` 経過すると、押下中でなくなる
+        let key = timeline.press(VK_Z, stale + 10);
+        assert!(!key.modifiers.ctrl);
+        assert!(!timeline.press(VK_LCONTROL, stale + 20).repeat);
+    }
+
+    #[test]
+    fn alt_gr_is_not_a_shortcut() {
+        //! AltGr (右 Alt) との同時押しは文字の入力とし、ショートカットとしないこと
+        let mut timeline = Timeline::new();
+        timeline.press(VK_RMENU, 0);
+        let key = timeline.press(b'Q' as u32, 10);
+        assert!(!key.modifiers.alt && !key.modifiers.is_shortcut());
+        assert!(key.is_text());
+    }
+
+    #[test]
+    fn alt_gr_with_left_ctrl_is_not_a_shortcut() {
+        //! Windows の AltGr (左 Ctrl + 右 Alt) も、ショートカットとしないこと
+        let mut timeline = Timeline::new();
+        timeline.press(VK_LCONTROL, 0);
+        timeline.press(VK_RMENU, 0);
+        let key = timeline.press(b'E' as u32, 10);
+        assert!(!key.modifiers.ctrl && !key.modifiers.is_shortcut());
+        assert!(key.is_text());
+
+        // AltGr を離した後の左 Ctrl は、通常の Ctrl とする
+        timeline.release(VK_RMENU, 20);
+        let key = timeline.press(VK_Z, 30);
+        assert!(key.modifiers.ctrl && key.modifiers.is_shortcut());
+    }
+
+    #[test]
+    fn left_alt_and_ctrl_are_shortcuts() {
+        //! 左 Alt や Ctrl との同時押しは、ショートカットとすること
+        let mut timeline = Timeline::new();
+        timeline.press(VK_LMENU, 0);
+        let key = timeline.press(b'F' as u32, 10);
+        assert!(key.modifiers.alt && !key.is_text());
+        timeline.release(VK_LMENU, 20);
+
+        timeline.press(VK_LCONTROL, 30);
+        let key = timeline.press(VK_Z, 40);
+        assert!(key.modifiers.ctrl && !key.is_text());
+    }
+}
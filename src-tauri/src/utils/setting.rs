@@ -12,6 +12,7 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::RwLock;
 
+use crate::utils::duration::{self, ConfigDuration};
 use crate::utils::keyhook;
 
 fn custom_format(
@@ -73,21 +74,306 @@ pub fn init_logger(is_debug_mode: &bool) {
     }
 }
 
+/// ミスタイプ修正の操作ごとの重み.
+/// 単語削除や元に戻すは複数文字の修正になることが多いため、1文字削除より重くする.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorrectionWeights {
+    pub backspace: f64,      // BackSpace
+    pub delete: f64,         // Delete
+    pub word_delete: f64,    // Ctrl+BackSpace, Ctrl+Delete
+    pub undo: f64,           // Ctrl+Z
+    pub select_replace: f64, // Shift+カーソル移動で選択した後の入力・削除
+}
+
+impl Default for CorrectionWeights {
+    fn default() -> Self {
+        Self {
+            backspace:      1.0,
+            delete:         1.0,
+            word_delete:    3.0,
+            undo:           2.0,
+            select_replace: 2.0,
+        }
+    }
+}
+
+/// ミスタイプ修正の連続に対する加重.
+/// 気付くのが遅れたミスタイプや大きな打ち直しは、すぐに1文字消す修正より疲れの兆候として強いため重くする.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LatencyConfig {
+    pub run_weight: f64, // 連続した修正の2回目以降に、1回ごとに加算する倍率 (0 で加重しない). 修正前に入力した文字数を超えた分は加算しない
+    pub max_scale: f64,  // 倍率の上限
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        Self {
+            run_weight: 0.25,
+            max_scale:  2.0,
+        }
+    }
+}
+
+/// ミスタイプ率を計算する範囲
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateWindow {
+    #[default]
+    Count, // 直近 `count` 回のキー入力
+    Time,  // 直近 `window_length` の打鍵時間 (休憩などで入力が途切れた時間は含めない)
+}
+
+/// ミスタイプが多いかどうかを判定する検出器の種類
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    Rate,   // 直近のキー入力に対するミスタイプ修正の割合
+    Burst,  // 短時間に集中したミスタイプ修正
+    Rhythm, // セッション開始時からの打鍵間隔の変化 (打鍵が遅くなる、リズムが乱れる)
+    Mash,   // キーの乱打や同じキーの連打 (ストレスの兆候)
+}
+
+/// 複数の検出器の判定の組み合わせ方
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Combine {
+    #[default]
+    Any, // いずれかの検出器が閾値を超えた場合
+    All, // 全ての検出器が閾値を超えた場合
+}
+
+/// 検出器の設定
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DetectorConfig {
+    pub strategies: Vec<Strategy>,       // 使用する検出器
+    pub combine: Combine,                // 判定の組み合わせ方
+    pub burst_count: usize,              // Burst: 時間窓内のミスタイプ修正の閾値
+    pub burst_window: ConfigDuration,    // Burst: 時間窓の長さ
+    pub rhythm_window: ConfigDuration,   // Rhythm: 現在の打鍵速度・間隔を計算する時間窓の長さ
+    pub rhythm_samples: usize,           // Rhythm: セッション開始時の基準とする打鍵間隔の数
+    pub rhythm_drift: f64,               // Rhythm: 基準からの打鍵間隔の平均・標準偏差の増加率の閾値
+    pub mash_keys: usize,                // Mash: 乱打かどうかを判定する、連続した押下の数
+    pub mash_interval: ConfigDuration,   // Mash: 乱打とみなす押下の平均間隔の上限
+    pub mash_entropy: f64,               // Mash: 乱打とみなす押下したキーの分布のエントロピー (bit) の下限
+    pub hammer_count: usize,             // Mash: 同じキーの連打とみなす押下の回数
+    pub hammer_interval: ConfigDuration, // Mash: 同じキーの連打とみなす押下の間隔の上限
+    pub mash_count: usize,               // Mash: 時間窓内の乱打と連打の回数の閾値
+    pub mash_window: ConfigDuration,     // Mash: 時間窓の長さ
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            strategies:      vec![Strategy::Rate],
+            combine:         Combine::Any,
+            burst_count:     5,
+            burst_window:    ConfigDuration::from_secs(10),
+            rhythm_window:   ConfigDuration::from_mins(2),
+            rhythm_samples:  100,
+            rhythm_drift:    0.3,
+            mash_keys:       8,
+            mash_interval:   ConfigDuration::from_millis(40),
+            mash_entropy:    2.5,
+            hammer_count:    6,
+            hammer_interval: ConfigDuration::from_millis(150),
+            mash_count:      3,
+            mash_window:     ConfigDuration::from_mins(1),
+        }
+    }
+}
+
+/// キーの分類表. 既定の分類から変更するキーを、仮想キーコードで指定する.
+/// 例えば、Tab を文字入力として数える場合は `text = [9]` とする.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeyClassTable {
+    pub text: Vec<u32>,       // 文字入力 (ミスタイプ率の分母に数える)
+    pub navigation: Vec<u32>, // カーソル移動
+    pub editing: Vec<u32>,    // 編集
+    pub modifier: Vec<u32>,   // 修飾キー (ミスタイプの判定に使用しない)
+    pub other: Vec<u32>,      // その他
+}
+
+/// 通知の設定
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub rearm_ratio: f64,         // 再通知の閾値 (閾値に対する倍率). 通知後、これを下回るまで通知しない
+    pub cooldown: ConfigDuration, // 通知後、次の通知までの最短時間
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            rearm_ratio: 0.8,
+            cooldown:    ConfigDuration::from_mins(15),
+        }
+    }
+}
+
+/// ミスタイプ率の閾値の決め方
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdMode {
+    #[default]
+    Fixed,    // 設定の `threshold` を閾値とする
+    Baseline, // 学習した普段のミスタイプ率から閾値を決める (学習中は `threshold` を使用する)
+}
+
+/// 普段のミスタイプ率の学習の設定
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BaselineConfig {
+    pub mode: ThresholdMode, // 閾値の決め方
+    pub alpha: f64,          // 指数移動平均の平滑化係数. 大きいほど直近の入力を重視する
+    pub sigma: f64,          // 普段の値から標準偏差の何倍離れたら閾値を超えたとするか
+    pub min_margin: f64,     // 閾値と普段の値の差の最小値 (ばらつきが小さい場合の誤検出を防ぐ)
+    pub min_blocks: u64,     // 学習した閾値を使用するまでに必要な学習回数
+}
+
+impl Default for BaselineConfig {
+    fn default() -> Self {
+        Self {
+            mode:       ThresholdMode::Fixed,
+            alpha:      0.05,
+            sigma:      2.0,
+            min_margin: 0.02,
+            min_blocks: 20,
+        }
+    }
+}
+
+/// IME の入力モードの間違い (半角で入力するつもりがひらがなで入力したなど) の検出の設定
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImeModeConfig {
+    pub notify: bool,       // 検出した場合に、入力モードの確認を促す通知を送るかどうか
+    pub min_typed: usize,   // 間違いとみなす入力の最小文字数
+    pub min_deleted: usize, // 間違いとみなす削除の最小文字数
+}
+
+impl Default for ImeModeConfig {
+    fn default() -> Self {
+        Self {
+            notify:      true,
+            min_typed:   4,
+            min_deleted: 4,
+        }
+    }
+}
+
+/// Caps Lock の誤操作 (Caps Lock を押したのに気付かずに入力し、削除して入力し直した) の検出の設定
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CapsLockConfig {
+    pub notify: bool,       // 検出した場合に、Caps Lock の確認を促す通知を送るかどうか
+    pub min_typed: usize,   // 誤操作とみなす、Caps Lock を切り替えた後に入力した英字の最小数
+    pub min_deleted: usize, // 誤操作とみなす削除の最小文字数
+}
+
+impl Default for CapsLockConfig {
+    fn default() -> Self {
+        Self {
+            notify:      true,
+            min_typed:   3,
+            min_deleted: 3,
+        }
+    }
+}
+
+/// マウスの入力の取得の設定.
+/// マウスで選択して入力し直した場合もミスタイプ修正として数え、マウスの操作中は休憩とみなさない.
+/// 入力ソースは起動時に選択するため、`enabled` の変更は再起動後に反映する.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PointerConfig {
+    pub enabled: bool,                   // マウスの入力を取得するかどうか
+    pub double_click: ConfigDuration,    // ダブルクリックとみなす、左ボタンの押下の間隔の上限
+    pub drag_distance: u32,              // クリックとみなす、押下から解放までのカーソルの移動量の上限 (ピクセル)
+    pub gesture_timeout: ConfigDuration, // クリックや選択の後、この時間内にキー入力がなければ、その操作をキー入力の判定に使用しない
+}
+
+impl Default for PointerConfig {
+    fn default() -> Self {
+        Self {
+            enabled:         false,
+            double_click:    ConfigDuration::from_millis(500),
+            drag_distance:   4,
+            gesture_timeout: ConfigDuration::from_secs(5),
+        }
+    }
+}
+
+/// キーボードの故障 (スイッチのチャタリング、押していないキーの連続入力) の検出の設定
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HardwareConfig {
+    pub notify: bool,                    // 故障が疑われるキーを検出した場合に、キーボードの点検を促す通知を送るかどうか
+    pub chatter_window: ConfigDuration,  // 同じキーの押下がこの時間内に続いた場合は、チャタリングとみなす
+    pub repeat_interval: ConfigDuration, // 同じキーの押下・解放がこの間隔より短く続いた場合は、押していない連続入力とみなす
+    pub repeat_count: usize,             // 押していない連続入力とみなす、同じキーの押下の最小回数
+    pub notify_after: usize,             // 1つのキーで、この回数だけ故障が疑われる入力を検出したら通知する
+}
+
+impl Default for HardwareConfig {
+    fn default() -> Self {
+        Self {
+            notify:          true,
+            chatter_window:  ConfigDuration::from_millis(30),
+            repeat_interval: ConfigDuration::from_millis(60),
+            repeat_count:    4,
+            notify_after:    3,
+        }
+    }
+}
+
+// 項目を追加しても古い設定ファイルを読み込めるように、存在しない項目は既定値とする
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
 struct MisstypeConfig {
     threshold: f64,
     count: usize,
-    interval: u64,
+    #[serde(deserialize_with = "duration::deserialize_legacy_minutes")]
+    interval: ConfigDuration,
     afterallow: bool,
+    weights: CorrectionWeights,
+    latency: LatencyConfig,
+    window: RateWindow,
+    window_length: ConfigDuration,
+    min_samples: usize,
+    detector: DetectorConfig,
+    key_classes: KeyClassTable,
+    alert: AlertConfig,
+    baseline: BaselineConfig,
+    ime_mode: ImeModeConfig,
+    caps_lock: CapsLockConfig,
+    pointer: PointerConfig,
+    hardware: HardwareConfig,
 }
 
 impl Default for MisstypeConfig {
     fn default() -> Self {
         Self {
-            threshold:  0.1,
-            count:      100,
-            interval:   5,
-            afterallow: true,
+            threshold:      0.1,
+            count:          100,
+            interval:       ConfigDuration::from_mins(5),
+            afterallow:     true,
+            weights:        CorrectionWeights::default(),
+            latency:        LatencyConfig::default(),
+            window:         RateWindow::Count,
+            window_length:  ConfigDuration::from_mins(10),
+            min_samples:    50,
+            detector:       DetectorConfig::default(),
+            key_classes:    KeyClassTable::default(),
+            alert:          AlertConfig::default(),
+            baseline:       BaselineConfig::default(),
+            ime_mode:       ImeModeConfig::default(),
+            caps_lock:      CapsLockConfig::default(),
+            pointer:        PointerConfig::default(),
+            hardware:       HardwareConfig::default(),
         }
     }
 }
@@ -117,7 +403,7 @@ impl MisstypeConfig {
             },
         }
 
-        return None;
+        None
     }
 
     pub fn save(&self) {
@@ -158,12 +444,12 @@ impl MisstypeConfig {
         self.count = value;
     }
 
-    pub fn get_interval(&self) -> u64 {
+    pub fn get_interval(&self) -> ConfigDuration {
         //! `interval` の取得用メソッド
         self.interval
     }
 
-    pub fn set_interval(&mut self, value: u64) {
+    pub fn set_interval(&mut self, value: ConfigDuration) {
         //! `interval` を更新
         self.interval = value;
     }
@@ -177,10 +463,144 @@ impl MisstypeConfig {
         //! `afterallow` を更新
         self.afterallow = value;
     }
+
+    pub fn get_correction_weights(&self) -> CorrectionWeights {
+        //! `weights` の取得用メソッド
+        self.weights
+    }
+
+    pub fn set_correction_weights(&mut self, value: CorrectionWeights) {
+        //! `weights` を更新
+        self.weights = value;
+    }
+
+    pub fn get_latency(&self) -> LatencyConfig {
+        //! `latency` の取得用メソッド
+        self.latency
+    }
+
+    pub fn set_latency(&mut self, value: LatencyConfig) {
+        //! `latency` を更新
+        self.latency = value;
+    }
+
+    pub fn get_window(&self) -> RateWindow {
+        //! `window` の取得用メソッド
+        self.window
+    }
+
+    pub fn set_window(&mut self, value: RateWindow) {
+        //! `window` を更新
+        self.window = value;
+    }
+
+    pub fn get_window_length(&self) -> ConfigDuration {
+        //! `window_length` の取得用メソッド
+        self.window_length
+    }
+
+    pub fn set_window_length(&mut self, value: ConfigDuration) {
+        //! `window_length` を更新
+        self.window_length = value;
+    }
+
+    pub fn get_min_samples(&self) -> usize {
+        //! `min_samples` の取得用メソッド
+        self.min_samples
+    }
+
+    pub fn set_min_samples(&mut self, value: usize) {
+        //! `min_samples` を更新
+        self.min_samples = value;
+    }
+
+    pub fn get_detector(&self) -> DetectorConfig {
+        //! `detector` の取得用メソッド
+        self.detector.clone()
+    }
+
+    pub fn set_detector(&mut self, value: DetectorConfig) {
+        //! `detector` を更新
+        self.detector = value;
+    }
+
+    pub fn get_key_classes(&self) -> KeyClassTable {
+        //! `key_classes` の取得用メソッド
+        self.key_classes.clone()
+    }
+
+    pub fn set_key_classes(&mut self, value: KeyClassTable) {
+        //! `key_classes` を更新
+        self.key_classes = value;
+    }
+
+    pub fn get_alert(&self) -> AlertConfig {
+        //! `alert` の取得用メソッド
+        self.alert
+    }
+
+    pub fn set_alert(&mut self, value: AlertConfig) {
+        //! `alert` を更新
+        self.alert = value;
+    }
+
+    pub fn get_baseline(&self) -> BaselineConfig {
+        //! `baseline` の取得用メソッド
+        self.baseline
+    }
+
+    pub fn set_baseline(&mut self, value: BaselineConfig) {
+        //! `baseline` を更新
+        self.baseline = value;
+    }
+
+    pub fn get_ime_mode(&self) -> ImeModeConfig {
+        //! `ime_mode` の取得用メソッド
+        self.ime_mode
+    }
+
+    pub fn set_ime_mode(&mut self, value: ImeModeConfig) {
+        //! `ime_mode` を更新
+        self.ime_mode = value;
+    }
+
+    pub fn get_caps_lock(&self) -> CapsLockConfig {
+        //! `caps_lock` の取得用メソッド
+        self.caps_lock
+    }
+
+    pub fn set_caps_lock(&mut self, value: CapsLockConfig) {
+        //! `caps_lock` を更新
+        self.caps_lock = value;
+    }
+
+    pub fn get_pointer(&self) -> PointerConfig {
+        //! `pointer` の取得用メソッド
+        self.pointer
+    }
+
+    pub fn set_pointer(&mut self, value: PointerConfig) {
+        //! `pointer` を更新
+        self.pointer = value;
+    }
+
+    pub fn get_hardware(&self) -> HardwareConfig {
+        //! `hardware` の取得用メソッド
+        self.hardware
+    }
+
+    pub fn set_hardware(&mut self, value: HardwareConfig) {
+        //! `hardware` を更新
+        self.hardware = value;
+    }
 }
 
 static CONFIG: Lazy<RwLock<MisstypeConfig>> = Lazy::new(|| {
-    RwLock::new(MisstypeConfig::load().unwrap_or_else(MisstypeConfig::default)) // configからの初期化に失敗した場合は default コンストラクタにより初期化する
+    // テストでは、開発者の設定ファイルを読み書きせずに既定値を使用する
+    if cfg!(test) {
+        return RwLock::new(MisstypeConfig::default());
+    }
+    RwLock::new(MisstypeConfig::load().unwrap_or_default()) // configからの初期化に失敗した場合は default コンストラクタにより初期化する
 });
 
 #[tauri::command]
@@ -201,9 +621,13 @@ pub fn get_threshold() -> f64 {
 #[tauri::command]
 pub fn set_threshold(value: f64) {
     //! グローバル変数 `CONFIG` の `threshold` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
     //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
-    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
-    cfg.set_threshold(value);
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_threshold(value);
+    }
+    keyhook::wake_monitor();
 }
 
 #[tauri::command]
@@ -216,26 +640,32 @@ pub fn get_count() -> usize {
 #[tauri::command]
 pub fn set_count(value: usize) {
     //! グローバル変数 `CONFIG` の `count` を更新するメソッド.
-    //! グローバル変数 `HISTORY` の `max_hisotry_size` も更新する.
+    //! ミスタイプ率の監視に即座に反映する.
     //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
-    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
-    cfg.set_count(value);
-    keyhook::change_max_history_size(value);
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_count(value);
+    }
+    keyhook::wake_monitor();
 }
 
 #[tauri::command]
-pub fn get_interval() -> u64 {
+pub fn get_interval() -> ConfigDuration {
     //! グローバル変数 `CONFIG` から `interval` を取得するメソッド
     let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
     cfg.get_interval()
 }
 
 #[tauri::command]
-pub fn set_interval(value: u64) {
+pub fn set_interval(value: ConfigDuration) {
     //! グローバル変数 `CONFIG` の `interval` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
     //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
-    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
-    cfg.set_interval(value);
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_interval(value);
+    }
+    keyhook::wake_monitor();
 }
 
 #[tauri::command]
@@ -252,3 +682,223 @@ pub fn set_afterallow(value: bool) {
     let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
     cfg.set_afterallow(value);
 }
+
+#[tauri::command]
+pub fn get_correction_weights() -> CorrectionWeights {
+    //! グローバル変数 `CONFIG` から `weights` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_correction_weights()
+}
+
+#[tauri::command]
+pub fn set_correction_weights(value: CorrectionWeights) {
+    //! グローバル変数 `CONFIG` の `weights` を更新するメソッド.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_correction_weights(value);
+}
+
+#[tauri::command]
+pub fn get_latency() -> LatencyConfig {
+    //! グローバル変数 `CONFIG` から `latency` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_latency()
+}
+
+#[tauri::command]
+pub fn set_latency(value: LatencyConfig) {
+    //! グローバル変数 `CONFIG` の `latency` を更新するメソッド.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_latency(value);
+}
+
+#[tauri::command]
+pub fn get_window() -> RateWindow {
+    //! グローバル変数 `CONFIG` から `window` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_window()
+}
+
+#[tauri::command]
+pub fn set_window(value: RateWindow) {
+    //! グローバル変数 `CONFIG` の `window` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_window(value);
+    }
+    keyhook::wake_monitor();
+}
+
+#[tauri::command]
+pub fn get_window_length() -> ConfigDuration {
+    //! グローバル変数 `CONFIG` から `window_length` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_window_length()
+}
+
+#[tauri::command]
+pub fn set_window_length(value: ConfigDuration) {
+    //! グローバル変数 `CONFIG` の `window_length` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_window_length(value);
+    }
+    keyhook::wake_monitor();
+}
+
+#[tauri::command]
+pub fn get_min_samples() -> usize {
+    //! グローバル変数 `CONFIG` から `min_samples` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_min_samples()
+}
+
+#[tauri::command]
+pub fn set_min_samples(value: usize) {
+    //! グローバル変数 `CONFIG` の `min_samples` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_min_samples(value);
+    }
+    keyhook::wake_monitor();
+}
+
+#[tauri::command]
+pub fn get_detector() -> DetectorConfig {
+    //! グローバル変数 `CONFIG` から `detector` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_detector()
+}
+
+#[tauri::command]
+pub fn set_detector(value: DetectorConfig) {
+    //! グローバル変数 `CONFIG` の `detector` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_detector(value);
+    }
+    keyhook::wake_monitor();
+}
+
+#[tauri::command]
+pub fn get_key_classes() -> KeyClassTable {
+    //! グローバル変数 `CONFIG` から `key_classes` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_key_classes()
+}
+
+#[tauri::command]
+pub fn set_key_classes(value: KeyClassTable) {
+    //! グローバル変数 `CONFIG` の `key_classes` を更新するメソッド.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_key_classes(value);
+}
+
+#[tauri::command]
+pub fn get_alert() -> AlertConfig {
+    //! グローバル変数 `CONFIG` から `alert` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_alert()
+}
+
+#[tauri::command]
+pub fn set_alert(value: AlertConfig) {
+    //! グローバル変数 `CONFIG` の `alert` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_alert(value);
+    }
+    keyhook::wake_monitor();
+}
+
+#[tauri::command]
+pub fn get_baseline() -> BaselineConfig {
+    //! グローバル変数 `CONFIG` から `baseline` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_baseline()
+}
+
+#[tauri::command]
+pub fn set_baseline(value: BaselineConfig) {
+    //! グローバル変数 `CONFIG` の `baseline` を更新するメソッド.
+    //! ミスタイプ率の監視に即座に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    {
+        let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+        cfg.set_baseline(value);
+    }
+    keyhook::wake_monitor();
+}
+
+#[tauri::command]
+pub fn get_ime_mode() -> ImeModeConfig {
+    //! グローバル変数 `CONFIG` から `ime_mode` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_ime_mode()
+}
+
+#[tauri::command]
+pub fn set_ime_mode(value: ImeModeConfig) {
+    //! グローバル変数 `CONFIG` の `ime_mode` を更新するメソッド.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_ime_mode(value);
+}
+
+#[tauri::command]
+pub fn get_caps_lock() -> CapsLockConfig {
+    //! グローバル変数 `CONFIG` から `caps_lock` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_caps_lock()
+}
+
+#[tauri::command]
+pub fn set_caps_lock(value: CapsLockConfig) {
+    //! グローバル変数 `CONFIG` の `caps_lock` を更新するメソッド.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_caps_lock(value);
+}
+
+#[tauri::command]
+pub fn get_pointer() -> PointerConfig {
+    //! グローバル変数 `CONFIG` から `pointer` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_pointer()
+}
+
+#[tauri::command]
+pub fn set_pointer(value: PointerConfig) {
+    //! グローバル変数 `CONFIG` の `pointer` を更新するメソッド.
+    //! `enabled` の変更は、次回の起動時に反映する.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_pointer(value);
+}
+
+#[tauri::command]
+pub fn get_hardware() -> HardwareConfig {
+    //! グローバル変数 `CONFIG` から `hardware` を取得するメソッド
+    let cfg = CONFIG.read().expect("CONFIG RwLock poisoned");
+    cfg.get_hardware()
+}
+
+#[tauri::command]
+pub fn set_hardware(value: HardwareConfig) {
+    //! グローバル変数 `CONFIG` の `hardware` を更新するメソッド.
+    //! 外部ファイルへは保存されないため、`save_config` 関数を必ず使用してください.
+    let mut cfg = CONFIG.write().expect("CONFIG RwLock poisoned");
+    cfg.set_hardware(value);
+}